@@ -2,15 +2,41 @@ use hc_mixin_elo::*;
 use hdk::prelude::holo_hash::*;
 use hdk::prelude::*;
 
+#[hdk_entry(id = "game")]
+#[derive(Clone)]
+pub struct Game {
+    players: Vec<AgentPubKeyB64>,
+    started_at: Timestamp,
+}
+
 #[derive(Serialize, Deserialize, Debug, SerializedBytes)]
 pub struct GameInfo2 {
+    // The hash of the game entry, which identifies the game for all its players
+    game_hash: EntryHashB64,
     opponent: AgentPubKeyB64,
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    season: Option<String>,
 }
 
 pub struct ChessEloRating;
 
 impl EloRatingSystem for ChessEloRating {
     type GameInfo = GameInfo2;
+    type RatingSystem = Elo;
+
+    fn game_id(game: &GameInfo2) -> String {
+        game.game_hash.to_string()
+    }
+
+    fn category(game: &GameInfo2) -> Option<String> {
+        game.category.clone()
+    }
+
+    fn season(game: &GameInfo2) -> Option<String> {
+        game.season.clone()
+    }
 
     fn validate_game_result(
         _game: GameInfo2,
@@ -18,9 +44,37 @@ impl EloRatingSystem for ChessEloRating {
     ) -> ExternResult<ValidateCallbackResult> {
         Ok(ValidateCallbackResult::Valid)
     }
+
+    fn validate_multiplayer_game_result(
+        _game: GameInfo2,
+        _result: MultiplayerGameResultInfo,
+    ) -> ExternResult<ValidateCallbackResult> {
+        Ok(ValidateCallbackResult::Valid)
+    }
+
+    fn validate_team_game_result(
+        _game: GameInfo2,
+        _result: TeamGameResultInfo,
+    ) -> ExternResult<ValidateCallbackResult> {
+        Ok(ValidateCallbackResult::Valid)
+    }
 }
 
-entry_defs![GameResult::entry_def(), PathEntry::entry_def()];
+entry_defs![
+    GameResult::entry_def(),
+    MultiplayerGameResult::entry_def(),
+    TeamGameResult::entry_def(),
+    GameResultDispute::entry_def(),
+    UnpublishedGameRetry::entry_def(),
+    GameResultProposal::entry_def(),
+    SentGameResultProposal::entry_def(),
+    QueuedGameResult::entry_def(),
+    QueuedGameResultEvent::entry_def(),
+    BlockedAgent::entry_def(),
+    InactivityDecay::entry_def(),
+    Game::entry_def(),
+    PathEntry::entry_def()
+];
 
 mixin_elo!(ChessEloRating);
 
@@ -41,23 +95,64 @@ fn post_commit(headers: Vec<SignedHeaderHashed>) {
 }
 
 #[hdk_extern]
-pub fn publish_result(result: (AgentPubKeyB64, f32)) -> ExternResult<EntryHashB64> {
-    attempt_create_countersigned_game_result::<ChessEloRating>(
+pub fn create_game(opponent: AgentPubKeyB64) -> ExternResult<EntryHashB64> {
+    let my_pub_key = AgentPubKeyB64::from(agent_info()?.agent_latest_pubkey);
+
+    let game = Game {
+        players: vec![my_pub_key, opponent],
+        started_at: sys_time()?,
+    };
+    create_entry(game.clone())?;
+
+    Ok(hash_entry(game)?.into())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PublishResultInput {
+    game_hash: EntryHashB64,
+    opponent: AgentPubKeyB64,
+    my_score: f32,
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    season: Option<String>,
+}
+
+impl PublishResultInput {
+    fn game_info(&self) -> GameInfo2 {
         GameInfo2 {
-            opponent: result.0.clone(),
-        },
-        result.0,
-        result.1,
+            game_hash: self.game_hash.clone(),
+            opponent: self.opponent.clone(),
+            category: self.category.clone(),
+            season: self.season.clone(),
+        }
+    }
+}
+
+#[hdk_extern]
+pub fn publish_result(input: PublishResultInput) -> ExternResult<EntryHashB64> {
+    attempt_create_countersigned_game_result::<ChessEloRating>(
+        input.game_info(),
+        input.opponent,
+        input.my_score,
     )
 }
 
 #[hdk_extern]
-pub fn publish_game_result_and_flag(result: (AgentPubKeyB64, f32)) -> ExternResult<EntryHashB64> {
-    create_game_result_and_flag::<ChessEloRating>(
-        GameInfo2 {
-            opponent: result.0.clone(),
-        },
-        result.0,
-        result.1,
+pub fn publish_game_result_and_flag(input: PublishResultInput) -> ExternResult<EntryHashB64> {
+    create_game_result_and_flag::<ChessEloRating>(input.game_info(), input.opponent, input.my_score)
+}
+
+#[hdk_extern]
+pub fn publish_game_result(input: PublishResultInput) -> ExternResult<PublishGameResultOutcome> {
+    hc_mixin_elo::publish_game_result::<ChessEloRating>(
+        input.game_info(),
+        input.opponent,
+        input.my_score,
     )
 }
+
+#[hdk_extern]
+pub fn queue_result(input: PublishResultInput) -> ExternResult<HeaderHashB64> {
+    queue_game_result::<ChessEloRating>(input.game_info(), input.opponent, input.my_score)
+}
@@ -0,0 +1,138 @@
+use hdk::prelude::holo_hash::*;
+use hdk::prelude::*;
+use std::collections::BTreeMap;
+
+use crate::game_result::{handlers::get_game_results_for_agents, AnyGameResult};
+
+/**
+ * Private record of an agent I refuse to play or countersign with
+ */
+#[hdk_entry(id = "blocked_agent", visibility = "private")]
+#[derive(Clone)]
+pub struct BlockedAgent {
+    pub agent: AgentPubKeyB64,
+    pub reason: Option<String>,
+}
+
+impl BlockedAgent {
+    pub fn entry_type() -> ExternResult<EntryType> {
+        Ok(EntryType::App(AppEntryType::new(
+            entry_def_index!(BlockedAgent)?,
+            zome_info()?.id,
+            EntryVisibility::Private,
+        )))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockAgentInput {
+    pub agent: AgentPubKeyB64,
+    pub reason: Option<String>,
+}
+
+pub fn block_agent(input: BlockAgentInput) -> ExternResult<()> {
+    if is_blocked(&input.agent)? {
+        return Ok(());
+    }
+
+    create_entry(BlockedAgent {
+        agent: input.agent,
+        reason: input.reason,
+    })?;
+
+    Ok(())
+}
+
+pub fn unblock_agent(agent: AgentPubKeyB64) -> ExternResult<()> {
+    for (header_hash, blocked_agent) in get_my_blocked_agents()? {
+        if blocked_agent.agent.eq(&agent) {
+            delete_entry(header_hash)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn get_blocked_agents() -> ExternResult<Vec<BlockedAgent>> {
+    Ok(get_my_blocked_agents()?
+        .into_iter()
+        .map(|(_, blocked_agent)| blocked_agent)
+        .collect())
+}
+
+pub(crate) fn is_blocked(agent: &AgentPubKeyB64) -> ExternResult<bool> {
+    Ok(get_my_blocked_agents()?
+        .iter()
+        .any(|(_, blocked_agent)| blocked_agent.agent.eq(agent)))
+}
+
+fn get_my_blocked_agents() -> ExternResult<Vec<(HeaderHash, BlockedAgent)>> {
+    let filter = ChainQueryFilter::new()
+        .entry_type(BlockedAgent::entry_type()?)
+        .include_entries(true);
+    let deleted_filter = ChainQueryFilter::new().header_type(HeaderType::Delete);
+
+    let deleted_headers: Vec<HeaderHash> = query(deleted_filter)?
+        .into_iter()
+        .filter_map(|element| match element.header() {
+            Header::Delete(delete) => Some(delete.deletes_address.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let mut blocked_agents = Vec::new();
+
+    for element in query(filter)? {
+        if deleted_headers.contains(element.header_address()) {
+            continue;
+        }
+        if let Some(blocked_agent) = element.entry().to_app_option::<BlockedAgent>()? {
+            blocked_agents.push((element.header_address().clone(), blocked_agent));
+        }
+    }
+
+    Ok(blocked_agents)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MarkedGameResult {
+    pub header: HeaderHashed,
+    pub game_result: AnyGameResult,
+    // Whether any of the players of this game result is in my block list
+    pub with_blocked_agent: bool,
+}
+
+/**
+ * Same as get_game_results_for_agents, but marking the game results played with the agents I have blocked
+ *
+ * The ratings are not changed, since they are shared by all the agents of the network
+ */
+pub fn get_marked_game_results_for_agents(
+    agent_pub_keys: Vec<AgentPubKeyB64>,
+) -> ExternResult<BTreeMap<AgentPubKeyB64, Vec<MarkedGameResult>>> {
+    let blocked_agents: Vec<AgentPubKeyB64> = get_blocked_agents()?
+        .into_iter()
+        .map(|blocked_agent| blocked_agent.agent)
+        .collect();
+
+    let game_results = get_game_results_for_agents(agent_pub_keys)?;
+
+    Ok(game_results
+        .into_iter()
+        .map(|(agent, game_results)| {
+            let marked_game_results = game_results
+                .into_iter()
+                .map(|(header, game_result)| MarkedGameResult {
+                    with_blocked_agent: game_result
+                        .agents()
+                        .iter()
+                        .any(|player| blocked_agents.contains(player)),
+                    header,
+                    game_result,
+                })
+                .collect();
+
+            (agent, marked_game_results)
+        })
+        .collect())
+}
@@ -0,0 +1,64 @@
+use hdk::prelude::*;
+use std::collections::BTreeMap;
+
+use crate::{
+    game_result::{
+        handlers::{element_to_any_game_result, my_game_result_elements},
+        AnyGameResult,
+    },
+    EloRatingSystem,
+};
+
+pub(crate) fn category_for<S: EloRatingSystem>(
+    game_info: &SerializedBytes,
+) -> ExternResult<Option<String>> {
+    let info = S::GameInfo::try_from(game_info.clone())
+        .or(Err(WasmError::Guest("Could not convert game info".into())))?;
+
+    Ok(S::category(&info))
+}
+
+pub(crate) fn category_of<S: EloRatingSystem>(
+    game_result: &AnyGameResult,
+) -> ExternResult<Option<String>> {
+    category_for::<S>(&game_result.game_info())
+}
+
+/**
+ * The game results in my source chain grouped by their category, each group in the order in which they were committed
+ */
+pub(crate) fn my_game_results_by_category<S: EloRatingSystem>(
+) -> ExternResult<BTreeMap<Option<String>, Vec<Element>>> {
+    let mut my_game_results_by_category: BTreeMap<Option<String>, Vec<Element>> = BTreeMap::new();
+
+    for element in my_game_result_elements()? {
+        let (_, game_result) = element_to_any_game_result(element.clone())?;
+
+        my_game_results_by_category
+            .entry(category_of::<S>(&game_result)?)
+            .or_insert_with(Vec::new)
+            .push(element);
+    }
+
+    Ok(my_game_results_by_category)
+}
+
+/**
+ * Keeps only the game result elements of the given category, in the same order
+ */
+pub(crate) fn elements_in_category<S: EloRatingSystem>(
+    game_result_elements: Vec<Element>,
+    category: &Option<String>,
+) -> ExternResult<Vec<Element>> {
+    let mut in_category: Vec<Element> = Vec::new();
+
+    for element in game_result_elements {
+        let (_, game_result) = element_to_any_game_result(element.clone())?;
+
+        if category_of::<S>(&game_result)?.eq(category) {
+            in_category.push(element);
+        }
+    }
+
+    Ok(in_category)
+}
@@ -1,31 +1,84 @@
+use hdk::prelude::holo_hash::*;
 use hdk::prelude::*;
 
-use crate::game_result::GameResult;
+use crate::{
+    block_list::is_blocked,
+    category::category_of,
+    countersigning::{grants::have_i_granted, queue::get_publication_queue},
+    elo_rating_system::EloRatingSystem,
+    game_result::{
+        game_id::{game_id_for, have_i_published_game, is_game_indexed},
+        handlers::{
+            element_to_any_game_result, get_last_game_result_for_agents, get_my_last_game_result,
+            internal_build_new_game_result, my_game_result_elements,
+        },
+        multiplayer::internal_build_new_multiplayer_game_result,
+        team::internal_build_new_team_game_result,
+        AnyGameResult, EloSignal, EloUpdate, GameResult,
+    },
+    remote_signal::EloRemoteSignal,
+};
 
 pub(crate) fn build_game_result_preflight(
     game_result: &GameResult,
+    session_duration_in_millis: u64,
 ) -> ExternResult<PreflightRequest> {
     let game_result_hash = hash_entry(game_result.clone())?;
 
-    let times = session_times_from_millis(5_000)?;
+    let opponent_address = game_result.opponent()?;
 
-    let agent_info = agent_info()?;
+    let bytes = SerializedBytes::try_from(game_result.clone())?;
 
-    let header_base = HeaderBase::Create(CreateBase::new(GameResult::entry_type()?));
+    build_preflight_request(
+        game_result_hash,
+        GameResult::entry_type()?,
+        vec![opponent_address],
+        bytes,
+        session_duration_in_millis,
+    )
+}
 
-    let opponent_address = game_result.opponent()?;
+pub(crate) fn build_any_game_result_preflight<S: EloRatingSystem>(
+    game_result: &AnyGameResult,
+) -> ExternResult<PreflightRequest> {
+    let game_result_hash = hash_entry(game_result.entry()?)?;
 
-    let countersigning_agents = vec![
-        (agent_info.agent_latest_pubkey, vec![]),
-        (opponent_address.clone().into(), vec![]),
-    ];
+    let opponents = game_result.opponents()?;
 
-    let bytes = SerializedBytes::try_from(game_result.clone())?;
+    let bytes = game_result.app_entry_bytes()?.into_sb();
+
+    build_preflight_request(
+        game_result_hash,
+        game_result.entry_type()?,
+        opponents,
+        bytes,
+        S::countersigning_session_duration_in_millis(),
+    )
+}
+
+fn build_preflight_request(
+    entry_hash: EntryHash,
+    entry_type: EntryType,
+    opponents: Vec<AgentPubKeyB64>,
+    bytes: SerializedBytes,
+    session_duration_in_millis: u64,
+) -> ExternResult<PreflightRequest> {
+    let times = session_times_from_millis(session_duration_in_millis)?;
+
+    let agent_info = agent_info()?;
+
+    let header_base = HeaderBase::Create(CreateBase::new(entry_type));
+
+    // I'm always the first agent, so the responses must be in the same order as the opponents
+    let mut countersigning_agents = vec![(agent_info.agent_latest_pubkey, vec![])];
+    for opponent in opponents {
+        countersigning_agents.push((opponent.into(), vec![]));
+    }
 
     let preflight_bytes = PreflightBytes(bytes.bytes().to_vec());
 
     let preflight_request = PreflightRequest::try_new(
-        game_result_hash,
+        entry_hash,
         countersigning_agents,
         Some(0),
         times,
@@ -36,3 +89,272 @@ pub(crate) fn build_game_result_preflight(
 
     Ok(preflight_request)
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "content")]
+pub enum GameResultRejection {
+    // The agent receiving the request is not one of the players of the game
+    NotAPlayer,
+    // The previous_game_result of this player is not their latest game result
+    OutdatedPreviousGameResult { agent: AgentPubKeyB64 },
+    // The EloUpdate of this player doesn't match the one computed from their previous game result
+    InvalidEloUpdate { agent: AgentPubKeyB64 },
+    // The game result was rejected by the EloRatingSystem
+    InvalidGameResult(String),
+    // A game result for this game has already been published
+    GameAlreadyPublished { game_id: String },
+    // The agent receiving the request has blocked one of the players
+    Blocked,
+    // The agent receiving the acceptance of a proposal never proposed that game result
+    NotProposed,
+    // The game result needs the approval of the user of the agent receiving the request, so it must be proposed
+    // with propose_game_result instead of countersigned right away
+    ApprovalRequired,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", content = "content")]
+pub enum PublishGameResultResponse {
+    Accepted(PreflightResponse),
+    Rejected(GameResultRejection),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", content = "content")]
+pub enum ProposeGameResultResponse {
+    // The game result was stored as a proposal, and a new session will be started if the user accepts it
+    PendingApproval,
+    Rejected(GameResultRejection),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "content")]
+pub enum CountersigningError {
+    // The session window ended before the countersigned entry was committed, so nothing was published
+    SessionExpired,
+    // One of the opponents checked the game result and rejected it
+    Rejected {
+        agent: AgentPubKeyB64,
+        rejection: GameResultRejection,
+    },
+    // One of the opponents couldn't be reached, or failed while handling the request
+    OpponentUnreachable {
+        agent: AgentPubKeyB64,
+        error: String,
+    },
+    // I couldn't take part in the session, e.g. because my source chain was still locked by a previous one
+    PreflightNotAccepted {
+        error: String,
+    },
+    // Every agent accepted the session, but I couldn't commit the countersigned entry before its window ended
+    CommitFailed {
+        error: String,
+    },
+}
+
+impl From<CountersigningError> for WasmError {
+    fn from(error: CountersigningError) -> Self {
+        WasmError::Guest(format!("{:?}", error))
+    }
+}
+
+pub(crate) fn is_session_expired(preflight_request: &PreflightRequest) -> ExternResult<bool> {
+    Ok(sys_time()?.as_micros() > preflight_request.session_times().end().as_micros())
+}
+
+/**
+ * When my source chain is unlocked after the session failed with the given error, or None if the session never locked it
+ *
+ * Only a preflight request that I accepted locks my source chain, until the end of the session window
+ */
+pub(crate) fn locked_until(
+    preflight_request: &PreflightRequest,
+    error: &CountersigningError,
+) -> Option<Timestamp> {
+    match error {
+        CountersigningError::PreflightNotAccepted { .. } => None,
+        _ => Some(preflight_request.session_times().end().clone()),
+    }
+}
+
+/**
+ * Abandons a failed session without committing anything, returning when my source chain is unlocked
+ *
+ * The hdk can't release the lock before the end of the session window, so this only lets the other agents know when
+ * their chains unlock: nothing else can be committed until then, so the callers have to retry after that time
+ * instead of waiting for it in the same zome call
+ */
+pub(crate) fn abandon_countersigning_session(
+    preflight_request: &PreflightRequest,
+    error: &CountersigningError,
+) -> ExternResult<Timestamp> {
+    let my_pub_key = agent_info()?.agent_latest_pubkey;
+
+    let game_result_hash: EntryHashB64 = preflight_request.app_entry_hash().clone().into();
+    let unlocks_at = preflight_request.session_times().end().clone();
+
+    let other_agents: Vec<AgentPubKey> = preflight_request
+        .signing_agents()
+        .iter()
+        .map(|(agent, _)| agent.clone())
+        .filter(|agent| !agent.eq(&my_pub_key))
+        .collect();
+
+    remote_signal(
+        EloRemoteSignal::CountersigningSessionAbandoned {
+            game_result_hash: game_result_hash.clone(),
+            unlocks_at: unlocks_at.clone(),
+            error: error.clone(),
+        },
+        other_agents,
+    )?;
+    emit_signal(EloSignal::CountersigningSessionAbandoned {
+        game_result_hash,
+        unlocks_at: unlocks_at.clone(),
+        error: error.clone(),
+    })?;
+
+    Ok(unlocks_at)
+}
+
+/**
+ * Whether the given agent may be countersigning a game result with me: an opponent I have granted access to my
+ * countersigning functions, one whose game result is in my publication queue, or one I have already played with
+ */
+pub(crate) fn is_known_counterparty<S: EloRatingSystem>(
+    agent: &AgentPubKeyB64,
+) -> ExternResult<bool> {
+    if have_i_granted(&agent.clone().into())? {
+        return Ok(true);
+    }
+
+    if get_publication_queue::<S>()?
+        .iter()
+        .any(|queued_game_result| queued_game_result.opponent.eq(agent))
+    {
+        return Ok(true);
+    }
+
+    for element in my_game_result_elements()? {
+        let (_, game_result) = element_to_any_game_result(element)?;
+
+        if game_result.elo_update_for(agent).is_some() {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/**
+ * Rebuilds the proposed game result from my own view of the latest game results of all its players,
+ * returning the reason to reject it if it doesn't match
+ *
+ * My own latest game result is read from my source chain, since the links to it may not have been gossiped yet
+ */
+pub(crate) fn check_proposed_game_result<S: EloRatingSystem>(
+    game_result: &AnyGameResult,
+) -> ExternResult<Option<GameResultRejection>> {
+    let opponents = match game_result.opponents() {
+        Ok(opponents) => opponents,
+        Err(_) => return Ok(Some(GameResultRejection::NotAPlayer)),
+    };
+    for opponent in opponents {
+        if is_blocked(&opponent)? {
+            return Ok(Some(GameResultRejection::Blocked));
+        }
+    }
+
+    let game_id = game_result.game_id();
+    if !game_id.eq(&game_id_for::<S>(&game_result.game_info())?) {
+        return Ok(Some(GameResultRejection::InvalidGameResult(
+            "The game id doesn't match the game info".into(),
+        )));
+    }
+    if have_i_published_game(&game_id)? || is_game_indexed(&game_id)? {
+        return Ok(Some(GameResultRejection::GameAlreadyPublished { game_id }));
+    }
+
+    let agents = game_result.agents();
+    let category = category_of::<S>(&game_result)?;
+    let my_pub_key = AgentPubKeyB64::from(agent_info()?.agent_latest_pubkey);
+
+    let mut latest_game_results = get_last_game_result_for_agents(
+        agents
+            .iter()
+            .filter(|agent| !agent.eq(&&my_pub_key))
+            .cloned()
+            .collect(),
+        &category,
+    )?;
+    latest_game_results.insert(my_pub_key, get_my_last_game_result::<S>(&category)?);
+
+    let previous_game_result_for =
+        |agent: &AgentPubKeyB64| latest_game_results.get(agent).cloned().unwrap_or(None);
+    let previous_game_results_for = |elo_updates: &Vec<EloUpdate>| {
+        elo_updates
+            .iter()
+            .map(|elo_update| previous_game_result_for(&elo_update.player_address))
+            .collect()
+    };
+
+    let recomputed_game_result = match game_result {
+        AnyGameResult::OneVsOne(game_result) => internal_build_new_game_result::<S>(
+            game_result.game_info.clone(),
+            &game_result.player_a.player_address,
+            &game_result.player_b.player_address,
+            game_result.score_player_a,
+            previous_game_result_for(&game_result.player_a.player_address),
+            previous_game_result_for(&game_result.player_b.player_address),
+        )
+        .map(AnyGameResult::OneVsOne),
+        AnyGameResult::Multiplayer(game_result) => internal_build_new_multiplayer_game_result::<S>(
+            game_result.game_info.clone(),
+            &game_result.agents(),
+            game_result.placements.clone(),
+            previous_game_results_for(&game_result.players),
+        )
+        .map(AnyGameResult::Multiplayer),
+        AnyGameResult::Team(game_result) => {
+            let (team_a, team_b) = game_result.teams();
+            internal_build_new_team_game_result::<S>(
+                game_result.game_info.clone(),
+                &team_a,
+                &team_b,
+                game_result.score_team_a,
+                previous_game_results_for(&game_result.team_a),
+                previous_game_results_for(&game_result.team_b),
+            )
+            .map(AnyGameResult::Team)
+        }
+    };
+
+    let recomputed_game_result = match recomputed_game_result {
+        Ok(recomputed_game_result) => recomputed_game_result,
+        Err(WasmError::Guest(reason)) => {
+            return Ok(Some(GameResultRejection::InvalidGameResult(reason)))
+        }
+        Err(error) => return Err(error),
+    };
+
+    for agent in agents {
+        let proposed = game_result.elo_update_for(&agent);
+        let recomputed = recomputed_game_result.elo_update_for(&agent);
+
+        match (proposed, recomputed) {
+            (Some(proposed), Some(recomputed)) => {
+                if proposed.previous_game_result != recomputed.previous_game_result {
+                    return Ok(Some(GameResultRejection::OutdatedPreviousGameResult {
+                        agent,
+                    }));
+                }
+                if proposed != recomputed {
+                    return Ok(Some(GameResultRejection::InvalidEloUpdate { agent }));
+                }
+            }
+            _ => return Ok(Some(GameResultRejection::InvalidEloUpdate { agent })),
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,257 @@
+use hdk::prelude::holo_hash::*;
+use hdk::prelude::*;
+
+use crate::{
+    elo_rating_system::EloRatingSystem,
+    game_result::{
+        game_id::have_i_published_game,
+        handlers::{element_to_any_game_result, my_game_result_elements},
+    },
+    remote_signal::EloRemoteSignal,
+};
+
+const GRANT_TAG_PREFIX: &str = "elo_countersigning";
+
+/**
+ * The functions that other players call on me to countersign game results, or to propose them to me
+ */
+pub(crate) fn countersigning_functions() -> ExternResult<GrantedFunctions> {
+    let mut functions: GrantedFunctions = BTreeSet::new();
+    functions.insert((zome_info()?.name, "request_publish_game_result".into()));
+    functions.insert((
+        zome_info()?.name,
+        "request_publish_multiplayer_game_result".into(),
+    ));
+    functions.insert((zome_info()?.name, "commit_multiplayer_game_result".into()));
+    functions.insert((zome_info()?.name, "propose_game_result".into()));
+    functions.insert((zome_info()?.name, "check_game_result".into()));
+    Ok(functions)
+}
+
+fn grant_tag(game_id: &Option<String>) -> String {
+    match game_id {
+        Some(game_id) => format!("{}:{}", GRANT_TAG_PREFIX, game_id),
+        None => GRANT_TAG_PREFIX.into(),
+    }
+}
+
+fn game_id_from_tag(tag: &String) -> Option<String> {
+    tag.strip_prefix(&format!("{}:", GRANT_TAG_PREFIX))
+        .map(|game_id| game_id.to_string())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GrantCountersigningInput {
+    pub opponents: Vec<AgentPubKeyB64>,
+    // If given, the grant is revoked as soon as I publish a game result for this game
+    pub game_id: Option<String>,
+}
+
+/**
+ * Allows the given opponents to send me countersigning requests, and sends them the secret to do so
+ *
+ * Meant to be called by every player at the start of a game: I only accept the secrets of the opponents I have granted
+ */
+pub fn grant_countersigning_capability(input: GrantCountersigningInput) -> ExternResult<()> {
+    let secret = generate_cap_secret()?;
+
+    let assignees: BTreeSet<AgentPubKey> = input
+        .opponents
+        .iter()
+        .map(|opponent| AgentPubKey::from(opponent.clone()))
+        .collect();
+
+    create_cap_grant(CapGrantEntry {
+        tag: grant_tag(&input.game_id),
+        access: CapAccess::Assigned {
+            secret,
+            assignees: assignees.clone(),
+        },
+        functions: countersigning_functions()?,
+    })?;
+
+    remote_signal(
+        EloRemoteSignal::CountersigningCapabilityGranted {
+            secret,
+            game_id: input.game_id,
+        },
+        assignees.into_iter().collect(),
+    )?;
+
+    Ok(())
+}
+
+/**
+ * Stores the secret that the grantor sent me, to use it when I send them countersigning requests
+ *
+ * Only the opponents I have granted can send me their secret, and I send mine back whenever I store a new one:
+ * this way the first player to grant gets the secret of the other one even if theirs arrived too early
+ */
+pub(crate) fn receive_countersigning_capability(
+    grantor: AgentPubKey,
+    secret: CapSecret,
+    game_id: Option<String>,
+) -> ExternResult<()> {
+    let my_grant = active_countersigning_grants()?
+        .into_iter()
+        .rev()
+        .map(|(_, grant)| grant)
+        .find(|grant| assignees_of(grant).contains(&grantor))
+        .ok_or(WasmError::Guest(
+            "Only the opponents I have granted a countersigning capability can send me theirs"
+                .into(),
+        ))?;
+
+    if has_claim(&grantor, &secret)? {
+        return Ok(());
+    }
+
+    create_cap_claim(CapClaimEntry::new(
+        grant_tag(&game_id),
+        grantor.clone(),
+        secret,
+    ))?;
+
+    if let CapAccess::Assigned { secret, .. } = &my_grant.access {
+        remote_signal(
+            EloRemoteSignal::CountersigningCapabilityGranted {
+                secret: *secret,
+                game_id: game_id_from_tag(&my_grant.tag),
+            },
+            vec![grantor],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn has_claim(grantor: &AgentPubKey, secret: &CapSecret) -> ExternResult<bool> {
+    let filter = ChainQueryFilter::new()
+        .entry_type(EntryType::CapClaim)
+        .include_entries(true);
+
+    Ok(query(filter)?
+        .into_iter()
+        .any(|element| match element.entry().as_option() {
+            Some(Entry::CapClaim(claim)) => {
+                claim.grantor().eq(grantor) && claim.secret().eq(secret)
+            }
+            _ => false,
+        }))
+}
+
+/**
+ * Whether I have granted the given agent access to my countersigning functions
+ */
+pub(crate) fn have_i_granted(agent: &AgentPubKey) -> ExternResult<bool> {
+    Ok(active_countersigning_grants()?
+        .iter()
+        .any(|(_, grant)| assignees_of(grant).contains(agent)))
+}
+
+/**
+ * The latest secret that the given agent sent me, to call their countersigning functions
+ */
+pub(crate) fn cap_secret_for(agent: &AgentPubKeyB64) -> ExternResult<Option<CapSecret>> {
+    let grantor = AgentPubKey::from(agent.clone());
+
+    let filter = ChainQueryFilter::new()
+        .entry_type(EntryType::CapClaim)
+        .include_entries(true);
+
+    let secret =
+        query(filter)?
+            .into_iter()
+            .rev()
+            .find_map(|element| match element.entry().as_option() {
+                Some(Entry::CapClaim(claim)) if claim.grantor().eq(&grantor) => {
+                    Some(*claim.secret())
+                }
+                _ => None,
+            });
+
+    Ok(secret)
+}
+
+/**
+ * Revokes the countersigning grants that are no longer needed: the ones for games I already published,
+ * the per-opponent ones for which I have published a game result with all the opponents since,
+ * and the ones older than countersigning_grant_ttl_in_mins
+ */
+pub fn revoke_stale_countersigning_capabilities<S: EloRatingSystem>() -> ExternResult<()> {
+    let now = sys_time()?.as_micros();
+    let ttl_in_micros = S::countersigning_grant_ttl_in_mins() as i64 * 60 * 1_000_000;
+
+    let game_results = my_game_result_elements()?;
+
+    for (element, grant) in active_countersigning_grants()? {
+        let expired = now - element.header().timestamp().as_micros() > ttl_in_micros;
+
+        let published = match game_id_from_tag(&grant.tag) {
+            Some(game_id) => have_i_published_game(&game_id)?,
+            None => game_results
+                .iter()
+                .filter(|game_result| {
+                    game_result.header().header_seq() > element.header().header_seq()
+                })
+                .any(|game_result| played_with_all(game_result, &assignees_of(&grant))),
+        };
+
+        if expired || published {
+            delete_cap_grant(element.header_address().clone())?;
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * My countersigning grants that haven't been revoked, in the order in which they were created
+ */
+fn active_countersigning_grants() -> ExternResult<Vec<(Element, CapGrantEntry)>> {
+    let grant_elements = query(
+        ChainQueryFilter::new()
+            .entry_type(EntryType::CapGrant)
+            .include_entries(true),
+    )?;
+    let deleted_headers: Vec<HeaderHash> =
+        query(ChainQueryFilter::new().header_type(HeaderType::Delete))?
+            .into_iter()
+            .filter_map(|element| match element.header() {
+                Header::Delete(delete) => Some(delete.deletes_address.clone()),
+                _ => None,
+            })
+            .collect();
+
+    let grants = grant_elements
+        .into_iter()
+        .filter(|element| !deleted_headers.contains(element.header_address()))
+        .filter_map(|element| match element.entry().as_option() {
+            Some(Entry::CapGrant(grant)) if grant.tag.starts_with(GRANT_TAG_PREFIX) => {
+                let grant = grant.clone();
+                Some((element, grant))
+            }
+            _ => None,
+        })
+        .collect();
+
+    Ok(grants)
+}
+
+fn assignees_of(grant: &CapGrantEntry) -> BTreeSet<AgentPubKey> {
+    match &grant.access {
+        CapAccess::Assigned { assignees, .. } => assignees.clone(),
+        _ => BTreeSet::new(),
+    }
+}
+
+fn played_with_all(game_result_element: &Element, agents: &BTreeSet<AgentPubKey>) -> bool {
+    match element_to_any_game_result(game_result_element.clone()) {
+        Ok((_, game_result)) => agents.iter().all(|agent| {
+            game_result
+                .elo_update_for(&AgentPubKeyB64::from(agent.clone()))
+                .is_some()
+        }),
+        Err(_) => false,
+    }
+}
@@ -1,3 +1,7 @@
 pub mod receiver;
 pub mod sender;
-pub mod common;
\ No newline at end of file
+pub mod common;
+pub mod multiplayer;
+pub mod proposals;
+pub mod queue;
+pub mod grants;
\ No newline at end of file
@@ -0,0 +1,237 @@
+use hdk::prelude::holo_hash::*;
+use hdk::prelude::*;
+
+use crate::{
+    countersigning::{
+        common::{
+            abandon_countersigning_session, build_any_game_result_preflight,
+            check_proposed_game_result, is_session_expired, CountersigningError,
+            GameResultRejection, PublishGameResultResponse,
+        },
+        grants::cap_secret_for,
+    },
+    elo_rating_system::EloRatingSystem,
+    game_result::{
+        handlers::{bytes_to_any_game_result, create_countersigned_any_game_result},
+        multiplayer::build_new_multiplayer_game_result,
+        team::build_new_team_game_result,
+        AnyGameResult, MultiplayerGameResultInfo, TeamGameResultInfo,
+    },
+};
+
+/**
+ * Build a new MultiplayerGameResult for the finished game, and run the countersigning session with all the other players
+ */
+pub fn try_create_countersigned_multiplayer_game_result<S: EloRatingSystem>(
+    game_info: SerializedBytes,
+    players: Vec<AgentPubKeyB64>,
+    placements: Vec<u32>,
+) -> ExternResult<EntryHashB64> {
+    let new_game_result = build_new_multiplayer_game_result::<S>(game_info, players, placements)?;
+
+    send_publish_multiplayer_game_result_request::<S>(AnyGameResult::Multiplayer(new_game_result))
+}
+
+/**
+ * Build a new TeamGameResult for the finished game, and run the countersigning session with all the other players,
+ * teammates included
+ */
+pub fn try_create_countersigned_team_game_result<S: EloRatingSystem>(
+    game_info: SerializedBytes,
+    team_a: Vec<AgentPubKeyB64>,
+    team_b: Vec<AgentPubKeyB64>,
+    score_team_a: f32,
+) -> ExternResult<EntryHashB64> {
+    let new_game_result = build_new_team_game_result::<S>(game_info, team_a, team_b, score_team_a)?;
+
+    send_publish_multiplayer_game_result_request::<S>(AnyGameResult::Team(new_game_result))
+}
+
+/**
+ * Countersigning sessions for multiplayer and team game results
+ *
+ * With more than two agents the countersigning is done in two rounds:
+ * first we collect the preflight responses of every other player, and then we send all of them
+ * to every player so that they can commit the countersigned entry, committing it ourselves at the end
+ */
+pub fn send_publish_multiplayer_game_result_request<S: EloRatingSystem>(
+    new_game_result: AnyGameResult,
+) -> ExternResult<EntryHashB64> {
+    let preflight_request = build_any_game_result_preflight::<S>(&new_game_result)?;
+
+    let my_response = match accept_countersigning_preflight_request(preflight_request.clone())? {
+        PreflightRequestAcceptance::Accepted(response) => Ok(response),
+        _ => Err(WasmError::Guest(
+            "There was an error when building the preflight_request for the publishing of game result".into(),
+        )),
+    }?;
+
+    match run_multiplayer_session(&new_game_result, &preflight_request, my_response)? {
+        Ok(game_result_hash) => Ok(game_result_hash),
+        Err(error) => {
+            abandon_countersigning_session(&preflight_request, &error)?;
+            Err(error.into())
+        }
+    }
+}
+
+fn run_multiplayer_session(
+    new_game_result: &AnyGameResult,
+    preflight_request: &PreflightRequest,
+    my_response: PreflightResponse,
+) -> ExternResult<Result<EntryHashB64, CountersigningError>> {
+    let opponents = new_game_result.opponents()?;
+
+    // The responses need to be in the same order as the agents in the preflight request
+    let mut responses = vec![my_response.clone()];
+
+    for opponent in opponents.iter() {
+        let call_remote_result = call_remote(
+            AgentPubKey::from(opponent.clone()),
+            zome_info()?.name,
+            FunctionName("request_publish_multiplayer_game_result".into()),
+            cap_secret_for(opponent)?,
+            my_response.clone(),
+        )?;
+
+        match call_remote_result {
+            ZomeCallResponse::Ok(response) => match response.decode()? {
+                PublishGameResultResponse::Accepted(opponent_preflight_response) => {
+                    responses.push(opponent_preflight_response);
+                }
+                PublishGameResultResponse::Rejected(rejection) => {
+                    return Ok(Err(CountersigningError::Rejected {
+                        agent: opponent.clone(),
+                        rejection,
+                    }))
+                }
+            },
+            _ => {
+                return Ok(Err(CountersigningError::OpponentUnreachable {
+                    agent: opponent.clone(),
+                    error: format!(
+                        "There was an error calling the opponent's request_publish_multiplayer_game_result: {:?}",
+                        call_remote_result
+                    ),
+                }))
+            }
+        }
+    }
+
+    if is_session_expired(preflight_request)? {
+        return Ok(Err(CountersigningError::SessionExpired));
+    }
+
+    for opponent in opponents.iter() {
+        let call_remote_result = call_remote(
+            AgentPubKey::from(opponent.clone()),
+            zome_info()?.name,
+            FunctionName("commit_multiplayer_game_result".into()),
+            cap_secret_for(opponent)?,
+            responses.clone(),
+        )?;
+
+        if let ZomeCallResponse::Ok(_) = call_remote_result {
+            continue;
+        }
+
+        if is_session_expired(preflight_request)? {
+            return Ok(Err(CountersigningError::SessionExpired));
+        }
+
+        return Ok(Err(CountersigningError::OpponentUnreachable {
+            agent: opponent.clone(),
+            error: format!(
+                "There was an error calling the opponent's commit_multiplayer_game_result: {:?}",
+                call_remote_result
+            ),
+        }));
+    }
+
+    match create_countersigned_any_game_result(new_game_result.clone(), responses) {
+        Ok(game_result_hash) => Ok(Ok(game_result_hash)),
+        Err(_) if is_session_expired(preflight_request)? => {
+            Ok(Err(CountersigningError::SessionExpired))
+        }
+        Err(error) => Ok(Err(CountersigningError::CommitFailed {
+            error: format!("{:?}", error),
+        })),
+    }
+}
+
+/**
+ * Receives the publish multiplayer game result request, rebuilds it from our own view of the chains of all the players,
+ * and accepts the preflight request if it matches
+ */
+pub fn handle_request_publish_multiplayer_game_result<S: EloRatingSystem>(
+    initiator_preflight_response: PreflightResponse,
+) -> ExternResult<PublishGameResultResponse> {
+    let request = initiator_preflight_response.request();
+
+    let game_result = any_game_result_from_request(request)?;
+
+    if let Some(rejection) = check_proposed_game_result::<S>(&game_result)? {
+        return Ok(PublishGameResultResponse::Rejected(rejection));
+    }
+
+    let info = S::GameInfo::try_from(game_result.game_info()).or(Err(WasmError::Guest(
+        "Could not convert SerializedBytes into GameInfo".into(),
+    )))?;
+
+    let validation_output = match &game_result {
+        AnyGameResult::Multiplayer(game_result) => {
+            S::validate_multiplayer_game_result(info, MultiplayerGameResultInfo::new(game_result))
+        }
+        AnyGameResult::Team(game_result) => {
+            S::validate_team_game_result(info, TeamGameResultInfo::new(game_result))
+        }
+        AnyGameResult::OneVsOne(_) => Ok(ValidateCallbackResult::Invalid(
+            "One vs one game results are countersigned with request_publish_game_result".into(),
+        )),
+    };
+
+    match validation_output {
+        Ok(ValidateCallbackResult::Valid) => {}
+        _ => {
+            return Ok(PublishGameResultResponse::Rejected(
+                GameResultRejection::InvalidGameResult(format!("{:?}", validation_output)),
+            ))
+        }
+    }
+
+    match accept_countersigning_preflight_request(request.clone())? {
+        PreflightRequestAcceptance::Accepted(response) => {
+            Ok(PublishGameResultResponse::Accepted(response))
+        }
+        _ => Err(WasmError::Guest(
+            "There was an error accepting the publishing of game result".into(),
+        )),
+    }
+}
+
+/**
+ * Receives the preflight responses of all the players and commits the countersigned entry
+ */
+pub fn handle_commit_multiplayer_game_result(
+    responses: Vec<PreflightResponse>,
+) -> ExternResult<EntryHashB64> {
+    let request = responses
+        .first()
+        .ok_or(WasmError::Guest(
+            "Can't commit a multiplayer game result without preflight responses".into(),
+        ))?
+        .request();
+
+    let game_result = any_game_result_from_request(request)?;
+
+    // Fails if I'm not one of the players
+    game_result.opponents()?;
+
+    create_countersigned_any_game_result(game_result, responses)
+}
+
+fn any_game_result_from_request(request: &PreflightRequest) -> ExternResult<AnyGameResult> {
+    bytes_to_any_game_result(SerializedBytes::from(UnsafeBytes::from(
+        request.preflight_bytes().0.clone(),
+    )))
+}
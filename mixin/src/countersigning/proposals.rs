@@ -0,0 +1,352 @@
+use hdk::prelude::holo_hash::*;
+use hdk::prelude::*;
+
+use crate::{
+    countersigning::{
+        common::{
+            build_game_result_preflight, CountersigningError, GameResultRejection,
+            ProposeGameResultResponse, PublishGameResultResponse,
+        },
+        grants::cap_secret_for,
+        receiver::{accept_and_commit_game_result, check_game_result_request},
+        sender::request_countersignature,
+    },
+    elo_rating_system::EloRatingSystem,
+    game_result::{game_id::have_i_published_game, EloSignal, GameResult, GameResultInfo},
+    remote_signal::EloRemoteSignal,
+};
+
+/**
+ * Private record of a countersigning request waiting for the approval of the user
+ *
+ * No countersigning session is kept open meanwhile: when the user accepts it, a new session is started with the opponent
+ */
+#[hdk_entry(id = "game_result_proposal", visibility = "private")]
+#[derive(Clone)]
+pub struct GameResultProposal {
+    pub game_result_hash: EntryHashB64,
+    pub game_result: GameResult,
+    pub expires_at: Timestamp,
+}
+
+impl GameResultProposal {
+    pub fn entry_type() -> ExternResult<EntryType> {
+        Ok(EntryType::App(AppEntryType::new(
+            entry_def_index!(GameResultProposal)?,
+            zome_info()?.id,
+            EntryVisibility::Private,
+        )))
+    }
+}
+
+/**
+ * Private record of a game result I proposed to an opponent who approves their game results manually
+ *
+ * When they accept it they start a new countersigning session, which I only join for the game results I proposed
+ */
+#[hdk_entry(id = "sent_game_result_proposal", visibility = "private")]
+#[derive(Clone)]
+pub struct SentGameResultProposal {
+    pub game_result_hash: EntryHashB64,
+    pub opponent: AgentPubKeyB64,
+}
+
+impl SentGameResultProposal {
+    pub fn entry_type() -> ExternResult<EntryType> {
+        Ok(EntryType::App(AppEntryType::new(
+            entry_def_index!(SentGameResultProposal)?,
+            zome_info()?.id,
+            EntryVisibility::Private,
+        )))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingGameResultProposal {
+    pub game_result_hash: EntryHashB64,
+    pub game_result: GameResult,
+    pub expires_at: Timestamp,
+}
+
+/**
+ * Whether the EloRatingSystem requires the approval of the opponent's user for this game result
+ *
+ * Both players run the same EloRatingSystem, so I know it before contacting the opponent
+ */
+pub(crate) fn requires_manual_approval<S: EloRatingSystem>(
+    game_result: &GameResult,
+) -> ExternResult<bool> {
+    let info = S::GameInfo::try_from(game_result.game_info.clone()).or(Err(WasmError::Guest(
+        "Could not convert SerializedBytes into GameInfo".into(),
+    )))?;
+
+    Ok(S::requires_manual_approval(
+        info,
+        GameResultInfo::new(game_result),
+    ))
+}
+
+/**
+ * Proposes the game result to the opponent, who stores it until their user accepts it
+ *
+ * No countersigning session is started, so my source chain is not locked while their user decides, and the proposal
+ * is recorded before the opponent can accept it
+ */
+pub(crate) fn propose_game_result(
+    game_result: &GameResult,
+) -> ExternResult<Result<EntryHashB64, CountersigningError>> {
+    let game_result_hash = hash_entry(game_result.clone())?;
+    let opponent_address = game_result.opponent()?;
+
+    record_sent_game_result_proposal(game_result_hash.clone(), opponent_address.clone())?;
+
+    let call_remote_result = call_remote(
+        AgentPubKey::from(opponent_address.clone()),
+        zome_info()?.name,
+        FunctionName("propose_game_result".into()),
+        cap_secret_for(&opponent_address)?,
+        game_result.clone(),
+    );
+
+    match call_remote_result {
+        Ok(ZomeCallResponse::Ok(response)) => match response.decode()? {
+            ProposeGameResultResponse::PendingApproval => Ok(Ok(game_result_hash.into())),
+            ProposeGameResultResponse::Rejected(rejection) => {
+                Ok(Err(CountersigningError::Rejected {
+                    agent: opponent_address,
+                    rejection,
+                }))
+            }
+        },
+        _ => Ok(Err(CountersigningError::OpponentUnreachable {
+            agent: opponent_address,
+            error: format!(
+                "There was an error calling the opponent's propose_game_result: {:?}",
+                call_remote_result
+            ),
+        })),
+    }
+}
+
+/**
+ * Receives a game result proposed by the opponent, and stores it until my user accepts or rejects it
+ */
+pub fn handle_propose_game_result<S: EloRatingSystem>(
+    game_result: GameResult,
+) -> ExternResult<ProposeGameResultResponse> {
+    let proposer = AgentPubKeyB64::from(call_info()?.provenance);
+
+    // Only my opponent in the game result can propose it to me
+    match game_result.opponent() {
+        Ok(opponent) if opponent.eq(&proposer) => {}
+        _ => {
+            return Ok(ProposeGameResultResponse::Rejected(
+                GameResultRejection::NotAPlayer,
+            ))
+        }
+    }
+
+    if let Some(rejection) = check_game_result_request::<S>(&game_result)? {
+        return Ok(ProposeGameResultResponse::Rejected(rejection));
+    }
+
+    store_game_result_proposal::<S>(game_result)?;
+
+    Ok(ProposeGameResultResponse::PendingApproval)
+}
+
+fn store_game_result_proposal<S: EloRatingSystem>(game_result: GameResult) -> ExternResult<()> {
+    let ttl_in_micros = S::game_result_proposals_ttl_in_mins() as i64 * 60 * 1_000_000;
+
+    let proposal = GameResultProposal {
+        game_result_hash: hash_entry(game_result.clone())?.into(),
+        game_result,
+        expires_at: Timestamp::from_micros(sys_time()?.as_micros() + ttl_in_micros),
+    };
+
+    create_entry(proposal.clone())?;
+
+    emit_signal(EloSignal::GameResultProposed {
+        game_result_hash: proposal.game_result_hash,
+        game_result: proposal.game_result,
+        expires_at: proposal.expires_at,
+    })?;
+
+    Ok(())
+}
+
+pub(crate) fn record_sent_game_result_proposal(
+    game_result_hash: EntryHash,
+    opponent: AgentPubKeyB64,
+) -> ExternResult<()> {
+    create_entry(SentGameResultProposal {
+        game_result_hash: game_result_hash.into(),
+        opponent,
+    })?;
+
+    Ok(())
+}
+
+/**
+ * The proposals waiting for my approval that haven't expired nor been published yet
+ */
+pub fn get_pending_game_result_proposals() -> ExternResult<Vec<PendingGameResultProposal>> {
+    let now = sys_time()?.as_micros();
+
+    let mut pending_proposals = Vec::new();
+
+    for (_, proposal) in get_my_proposals()? {
+        if proposal.expires_at.as_micros() > now
+            && !have_i_published_game(&proposal.game_result.game_id)?
+        {
+            pending_proposals.push(PendingGameResultProposal {
+                game_result_hash: proposal.game_result_hash,
+                game_result: proposal.game_result,
+                expires_at: proposal.expires_at,
+            });
+        }
+    }
+
+    Ok(pending_proposals)
+}
+
+/**
+ * Accepts the proposal for the given game result, countersigning it with the opponent in a new session
+ *
+ * The proposal is not deleted, since the countersigned entry can't be committed together with other entries:
+ * it stops being pending once the game result is published
+ */
+pub fn accept_game_result_proposal<S: EloRatingSystem>(
+    game_result_hash: EntryHashB64,
+) -> ExternResult<EntryHashB64> {
+    let (_, proposal) = take_proposal(&game_result_hash)?;
+
+    if proposal.expires_at.as_micros() < sys_time()?.as_micros() {
+        return Err(WasmError::Guest("The proposal has expired".into()));
+    }
+
+    // Other game results may have been published since the proposal was received
+    if let Some(rejection) = check_game_result_request::<S>(&proposal.game_result)? {
+        return Err(WasmError::Guest(format!(
+            "The proposed game result is no longer valid: {:?}",
+            rejection
+        )));
+    }
+
+    let preflight_request = build_game_result_preflight(
+        &proposal.game_result,
+        S::countersigning_session_duration_in_millis(),
+    )?;
+
+    Ok(request_countersignature(
+        &proposal.game_result,
+        &preflight_request,
+        "commit_accepted_game_result",
+    )??)
+}
+
+/**
+ * Receives the acceptance of a game result I proposed, and commits my part of the countersigned entry
+ * if it's still valid
+ */
+pub fn handle_commit_accepted_game_result<S: EloRatingSystem>(
+    acceptor_preflight_response: PreflightResponse,
+) -> ExternResult<PublishGameResultResponse> {
+    let request = acceptor_preflight_response.request();
+
+    let game_result: GameResult =
+        SerializedBytes::from(UnsafeBytes::from(request.preflight_bytes().0.clone())).try_into()?;
+
+    if !have_i_proposed_to(&hash_entry(game_result.clone())?, &game_result.opponent()?)? {
+        return Ok(PublishGameResultResponse::Rejected(
+            GameResultRejection::NotProposed,
+        ));
+    }
+
+    if let Some(rejection) = check_game_result_request::<S>(&game_result)? {
+        return Ok(PublishGameResultResponse::Rejected(rejection));
+    }
+
+    let my_response = accept_and_commit_game_result(game_result, acceptor_preflight_response)?;
+
+    Ok(PublishGameResultResponse::Accepted(my_response))
+}
+
+/**
+ * Rejects the proposal for the given game result, and lets the opponent know
+ */
+pub fn reject_game_result_proposal(game_result_hash: EntryHashB64) -> ExternResult<()> {
+    let (header_hash, proposal) = take_proposal(&game_result_hash)?;
+
+    delete_entry(header_hash)?;
+
+    remote_signal(
+        EloRemoteSignal::GameResultProposalRejected { game_result_hash },
+        vec![proposal.game_result.opponent()?.into()],
+    )?;
+
+    Ok(())
+}
+
+/**
+ * Whether I proposed the given game result to the given opponent
+ */
+pub(crate) fn have_i_proposed_to(
+    game_result_hash: &EntryHash,
+    opponent: &AgentPubKeyB64,
+) -> ExternResult<bool> {
+    let filter = ChainQueryFilter::new()
+        .entry_type(SentGameResultProposal::entry_type()?)
+        .include_entries(true);
+
+    for element in query(filter)? {
+        if let Some(sent_proposal) = element.entry().to_app_option::<SentGameResultProposal>()? {
+            if EntryHash::from(sent_proposal.game_result_hash).eq(game_result_hash)
+                && sent_proposal.opponent.eq(opponent)
+            {
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+fn take_proposal(
+    game_result_hash: &EntryHashB64,
+) -> ExternResult<(HeaderHash, GameResultProposal)> {
+    get_my_proposals()?
+        .into_iter()
+        .find(|(_, proposal)| proposal.game_result_hash.eq(game_result_hash))
+        .ok_or(WasmError::Guest(
+            "There is no pending proposal for this game result".into(),
+        ))
+}
+
+fn get_my_proposals() -> ExternResult<Vec<(HeaderHash, GameResultProposal)>> {
+    let filter = ChainQueryFilter::new()
+        .entry_type(GameResultProposal::entry_type()?)
+        .include_entries(true);
+    let deleted_filter = ChainQueryFilter::new().header_type(HeaderType::Delete);
+
+    let deleted_headers: Vec<HeaderHash> = query(deleted_filter)?
+        .into_iter()
+        .filter_map(|element| match element.header() {
+            Header::Delete(delete) => Some(delete.deletes_address.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let mut proposals = Vec::new();
+
+    for element in query(filter)? {
+        if deleted_headers.contains(element.header_address()) {
+            continue;
+        }
+        if let Some(proposal) = element.entry().to_app_option::<GameResultProposal>()? {
+            proposals.push((element.header_address().clone(), proposal));
+        }
+    }
+
+    Ok(proposals)
+}
@@ -0,0 +1,313 @@
+use hdk::prelude::holo_hash::*;
+use hdk::prelude::*;
+
+use crate::{
+    countersigning::sender::{
+        publish_game_result_with_fallback, PublishGameResultOutcome, PublishPath,
+    },
+    elo_rating_system::EloRatingSystem,
+    game_result::{
+        game_id::{game_id_for, my_game_result_for_game},
+        handlers::build_new_game_result,
+        EloSignal,
+    },
+};
+
+/**
+ * Private record of a game result waiting to be published
+ *
+ * Only the inputs of the game result are stored, so that it's built from my real chain head when its turn comes
+ */
+#[hdk_entry(id = "queued_game_result", visibility = "private")]
+#[derive(Clone)]
+pub struct QueuedGameResult {
+    pub game_info: SerializedBytes,
+    pub opponent: AgentPubKeyB64,
+    pub my_score: f32,
+    pub queued_at: Timestamp,
+}
+
+/**
+ * Private record of the progress of a queued game result
+ */
+#[hdk_entry(id = "queued_game_result_event", visibility = "private")]
+#[derive(Clone)]
+pub struct QueuedGameResultEvent {
+    pub queued_game_result_hash: HeaderHashB64,
+    pub event: QueueEvent,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "content")]
+pub enum QueueEvent {
+    // The game result was picked to be published next, and is in flight until the given time at most
+    Started { until: Timestamp },
+    // The opponent has to approve the game result, which is in flight until the given time at most
+    AwaitingApproval { until: Timestamp },
+    Failed { error: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "content")]
+pub enum QueuedGameResultStatus {
+    Queued,
+    InFlight,
+    Published { game_result_hash: EntryHashB64 },
+    // The publication failed and won't be retried, e.g. because the opponent rejected the game result
+    Failed { error: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueuedGameResultState {
+    pub queued_game_result_hash: HeaderHashB64,
+    pub game_id: String,
+    pub opponent: AgentPubKeyB64,
+    pub my_score: f32,
+    pub queued_at: Timestamp,
+    pub status: QueuedGameResultStatus,
+}
+
+/**
+ * Adds the game result to my publication queue, from where the scheduled job publishes it
+ *
+ * Queued game results are committed with relaxed ordering, so concurrent calls don't fail each other
+ */
+pub fn enqueue_game_result<S: EloRatingSystem>(
+    game_info: SerializedBytes,
+    opponent: AgentPubKeyB64,
+    my_score: f32,
+) -> ExternResult<HeaderHashB64> {
+    // Fails early if the game info is not valid
+    game_id_for::<S>(&game_info)?;
+
+    let queued_game_result = QueuedGameResult {
+        game_info,
+        opponent,
+        my_score,
+        queued_at: sys_time()?,
+    };
+
+    let header_hash = create_relaxed(&queued_game_result, queued_game_result.clone().try_into()?)?;
+
+    Ok(header_hash.into())
+}
+
+/**
+ * Advances my publication queue by one step, called from the scheduled job
+ *
+ * The game result to publish next is first marked as started in its own call, and published in the next one:
+ * this way the in flight publication is always read from committed state, and the countersigned entry
+ * is not committed in the same call as any other entry
+ */
+pub fn process_publication_queue<S: EloRatingSystem>() -> ExternResult<()> {
+    let queue = get_publication_queue::<S>()?;
+    let events = get_queue_events()?;
+
+    let in_flight = queue
+        .iter()
+        .find(|state| matches!(state.status, QueuedGameResultStatus::InFlight));
+
+    if let Some(in_flight) = in_flight {
+        let last_event = events
+            .iter()
+            .filter(|event| {
+                event
+                    .queued_game_result_hash
+                    .eq(&in_flight.queued_game_result_hash)
+            })
+            .last();
+
+        // A game result awaiting the approval of the opponent is not published again
+        if let Some(QueuedGameResultEvent {
+            event: QueueEvent::Started { until },
+            ..
+        }) = last_event
+        {
+            let header_hash = HeaderHash::from(in_flight.queued_game_result_hash.clone());
+            publish_queued_game_result::<S>(header_hash, *until)?;
+        }
+
+        return Ok(());
+    }
+
+    let next = queue
+        .into_iter()
+        .find(|state| matches!(state.status, QueuedGameResultStatus::Queued));
+
+    if let Some(next) = next {
+        // Covers the wait until the next run of the scheduled job, which runs every minute, and the whole publication
+        let in_flight_for_in_micros = (60_000
+            + S::countersigning_timeout_in_millis()
+            + S::countersigning_session_duration_in_millis())
+            as i64
+            * 1_000;
+        let until = Timestamp::from_micros(sys_time()?.as_micros() + in_flight_for_in_micros);
+
+        record_event(
+            HeaderHash::from(next.queued_game_result_hash),
+            QueueEvent::Started { until },
+        )?;
+    }
+
+    Ok(())
+}
+
+fn publish_queued_game_result<S: EloRatingSystem>(
+    queued_game_result_hash: HeaderHash,
+    until: Timestamp,
+) -> ExternResult<()> {
+    let queued_game_result = get_queued_game_results()?
+        .into_iter()
+        .find(|(hash, _)| hash.eq(&queued_game_result_hash))
+        .map(|(_, queued_game_result)| queued_game_result)
+        .ok_or(WasmError::Guest(
+            "Unreachable: the queued game result is not in my source chain".into(),
+        ))?;
+
+    // Built now, so it's rebased on all the game results published before it
+    let result = build_new_game_result::<S>(
+        queued_game_result.game_info,
+        &queued_game_result.opponent,
+        queued_game_result.my_score,
+    )
+    .and_then(publish_game_result_with_fallback::<S>);
+
+    match result {
+        Ok(PublishGameResultOutcome {
+            path: PublishPath::PendingApproval,
+            ..
+        }) => record_event(
+            queued_game_result_hash,
+            QueueEvent::AwaitingApproval { until },
+        ),
+        // Nothing can be committed while the failed session locks my source chain, so no event is recorded:
+        // the scheduled job publishes the game result again in a later run
+        Ok(PublishGameResultOutcome {
+            path: PublishPath::Locked { .. },
+            ..
+        }) => Ok(()),
+        Ok(_) => Ok(()),
+        Err(error) => {
+            let error = format!("{:?}", error);
+
+            emit_signal(EloSignal::QueuedGameResultFailed {
+                queued_game_result_hash: queued_game_result_hash.clone().into(),
+                error: error.clone(),
+            })?;
+
+            record_event(queued_game_result_hash, QueueEvent::Failed { error })
+        }
+    }
+}
+
+/**
+ * All the game results I have queued, with their status
+ */
+pub fn get_publication_queue<S: EloRatingSystem>() -> ExternResult<Vec<QueuedGameResultState>> {
+    let events = get_queue_events()?;
+    let now = sys_time()?.as_micros();
+
+    let mut queue: Vec<QueuedGameResultState> = Vec::new();
+
+    for (header_hash, queued_game_result) in get_queued_game_results()? {
+        let game_id = game_id_for::<S>(&queued_game_result.game_info)?;
+        let queued_game_result_hash = HeaderHashB64::from(header_hash);
+
+        let last_event = events
+            .iter()
+            .filter(|event| event.queued_game_result_hash.eq(&queued_game_result_hash))
+            .last();
+
+        let status = match my_game_result_for_game(&game_id)? {
+            Some(game_result_hash) => QueuedGameResultStatus::Published {
+                game_result_hash: game_result_hash.into(),
+            },
+            None => match last_event.map(|event| event.event.clone()) {
+                Some(QueueEvent::Failed { error }) => QueuedGameResultStatus::Failed { error },
+                Some(QueueEvent::Started { until }) if until.as_micros() > now => {
+                    QueuedGameResultStatus::InFlight
+                }
+                Some(QueueEvent::AwaitingApproval { until }) if until.as_micros() > now => {
+                    QueuedGameResultStatus::InFlight
+                }
+                _ => QueuedGameResultStatus::Queued,
+            },
+        };
+
+        queue.push(QueuedGameResultState {
+            queued_game_result_hash,
+            game_id,
+            opponent: queued_game_result.opponent,
+            my_score: queued_game_result.my_score,
+            queued_at: queued_game_result.queued_at,
+            status,
+        });
+    }
+
+    Ok(queue)
+}
+
+fn record_event(queued_game_result_hash: HeaderHash, event: QueueEvent) -> ExternResult<()> {
+    let queue_event = QueuedGameResultEvent {
+        queued_game_result_hash: queued_game_result_hash.into(),
+        event,
+    };
+
+    create_relaxed(&queue_event, queue_event.clone().try_into()?)?;
+
+    Ok(())
+}
+
+fn create_relaxed<T>(entry: &T, bytes: AppEntryBytes) -> ExternResult<HeaderHash>
+where
+    EntryDefId: for<'a> From<&'a T>,
+{
+    HDK.with(|h| {
+        h.borrow().create(CreateInput::new(
+            entry.into(),
+            Entry::App(bytes),
+            ChainTopOrdering::Relaxed,
+        ))
+    })
+}
+
+// Elements are returned in chain order, so the queue is in the order in which the game results were queued
+fn get_queued_game_results() -> ExternResult<Vec<(HeaderHash, QueuedGameResult)>> {
+    let filter = ChainQueryFilter::new()
+        .entry_type(EntryType::App(AppEntryType::new(
+            entry_def_index!(QueuedGameResult)?,
+            zome_info()?.id,
+            EntryVisibility::Private,
+        )))
+        .include_entries(true);
+
+    let mut queued_game_results = Vec::new();
+
+    for element in query(filter)? {
+        if let Some(queued_game_result) = element.entry().to_app_option::<QueuedGameResult>()? {
+            queued_game_results.push((element.header_address().clone(), queued_game_result));
+        }
+    }
+
+    Ok(queued_game_results)
+}
+
+fn get_queue_events() -> ExternResult<Vec<QueuedGameResultEvent>> {
+    let filter = ChainQueryFilter::new()
+        .entry_type(EntryType::App(AppEntryType::new(
+            entry_def_index!(QueuedGameResultEvent)?,
+            zome_info()?.id,
+            EntryVisibility::Private,
+        )))
+        .include_entries(true);
+
+    let mut events = Vec::new();
+
+    for element in query(filter)? {
+        if let Some(event) = element.entry().to_app_option::<QueuedGameResultEvent>()? {
+            events.push(event);
+        }
+    }
+
+    Ok(events)
+}
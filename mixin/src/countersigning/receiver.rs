@@ -1,34 +1,109 @@
+use hdk::prelude::holo_hash::*;
 use hdk::prelude::*;
 
 use crate::{
+    countersigning::{
+        common::{check_proposed_game_result, GameResultRejection, PublishGameResultResponse},
+        proposals::requires_manual_approval,
+    },
     elo_rating_system::EloRatingSystem,
-    game_result::{handlers::create_countersigned_game_result, GameResult, GameResultInfo},
+    game_result::{
+        handlers::create_countersigned_game_result, AnyGameResult, GameResult, GameResultInfo,
+    },
 };
 
 /**
- * Receives the publish game result request, verifies that we don't have any latest game result, and creates the first part of the countersigned entry
+ * Receives the publish game result request, rebuilds the game result from our own view of both chains,
+ * and creates the first part of the countersigned entry if it matches
+ *
+ * If the EloRatingSystem requires manual approval for this game the request is rejected, since the game result must be
+ * proposed with propose_game_result instead
  */
 pub fn handle_request_publish_game_result<S: EloRatingSystem>(
     counterparty_preflight_response: PreflightResponse,
-) -> ExternResult<PreflightResponse> {
+) -> ExternResult<PublishGameResultResponse> {
     let request = counterparty_preflight_response.request();
 
     let game_result: GameResult =
         SerializedBytes::from(UnsafeBytes::from(request.preflight_bytes().0.clone())).try_into()?;
 
+    if let Some(rejection) = check_game_result_request::<S>(&game_result)? {
+        return Ok(PublishGameResultResponse::Rejected(rejection));
+    }
+
+    if requires_manual_approval::<S>(&game_result)? {
+        return Ok(PublishGameResultResponse::Rejected(
+            GameResultRejection::ApprovalRequired,
+        ));
+    }
+
+    let my_response = accept_and_commit_game_result(game_result, counterparty_preflight_response)?;
+
+    Ok(PublishGameResultResponse::Accepted(my_response))
+}
+
+/**
+ * Checks the game result that the opponent is about to countersign with me, before they start the session,
+ * returning the reason to reject it if I would
+ *
+ * This way the opponent doesn't lock their source chain if I can't be reached or would reject the game result
+ */
+pub fn handle_check_game_result<S: EloRatingSystem>(
+    game_result: GameResult,
+) -> ExternResult<Option<GameResultRejection>> {
+    let sender = AgentPubKeyB64::from(call_info()?.provenance);
+
+    // Only my opponent in the game result can ask me to check it
+    match game_result.opponent() {
+        Ok(opponent) if opponent.eq(&sender) => {}
+        _ => return Ok(Some(GameResultRejection::NotAPlayer)),
+    }
+
+    if let Some(rejection) = check_game_result_request::<S>(&game_result)? {
+        return Ok(Some(rejection));
+    }
+
+    if requires_manual_approval::<S>(&game_result)? {
+        return Ok(Some(GameResultRejection::ApprovalRequired));
+    }
+
+    Ok(None)
+}
+
+/**
+ * Checks the proposed game result against my view of both chains and the EloRatingSystem,
+ * returning the reason to reject it if it doesn't match
+ */
+pub(crate) fn check_game_result_request<S: EloRatingSystem>(
+    game_result: &GameResult,
+) -> ExternResult<Option<GameResultRejection>> {
+    if let Some(rejection) =
+        check_proposed_game_result::<S>(&AnyGameResult::OneVsOne(game_result.clone()))?
+    {
+        return Ok(Some(rejection));
+    }
+
     let info = S::GameInfo::try_from(game_result.game_info.clone()).or(Err(WasmError::Guest(
-        "Could not conver GameInfo into SerializedBytes".into(),
+        "Could not convert SerializedBytes into GameInfo".into(),
     )))?;
-    let game_result_info = GameResultInfo::new(&game_result);
+    let game_result_info = GameResultInfo::new(game_result);
 
     let validation_output = S::validate_game_result(info, game_result_info);
 
     match validation_output {
-        Ok(ValidateCallbackResult::Valid) => Ok(()),
-        _ => Err(WasmError::Guest(
-            format!("The game result that the opponent is trying to make me sign is actually not valid: {:?}", validation_output),
-        )),
-    }?;
+        Ok(ValidateCallbackResult::Valid) => Ok(None),
+        _ => Ok(Some(GameResultRejection::InvalidGameResult(format!(
+            "{:?}",
+            validation_output
+        )))),
+    }
+}
+
+pub(crate) fn accept_and_commit_game_result(
+    game_result: GameResult,
+    counterparty_preflight_response: PreflightResponse,
+) -> ExternResult<PreflightResponse> {
+    let request = counterparty_preflight_response.request();
 
     let my_response = match accept_countersigning_preflight_request(request.clone())? {
         PreflightRequestAcceptance::Accepted(response) => Ok(response),
@@ -42,4 +117,4 @@ pub fn handle_request_publish_game_result<S: EloRatingSystem>(
     create_countersigned_game_result(game_result, responses)?;
 
     Ok(my_response)
-}
\ No newline at end of file
+}
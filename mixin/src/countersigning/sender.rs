@@ -2,10 +2,20 @@ use hdk::prelude::holo_hash::*;
 use hdk::prelude::*;
 
 use crate::{
-    countersigning::common::build_game_result_preflight,
+    countersigning::{
+        common::{
+            abandon_countersigning_session, build_game_result_preflight, is_session_expired,
+            locked_until, CountersigningError, GameResultRejection, PublishGameResultResponse,
+        },
+        grants::cap_secret_for,
+        proposals::{propose_game_result, requires_manual_approval},
+    },
     elo_rating_system::EloRatingSystem,
     game_result::{
-        handlers::{build_new_game_result, create_countersigned_game_result},
+        handlers::{
+            build_new_game_result, create_countersigned_game_result,
+            create_unilateral_game_result_and_flag,
+        },
         GameResult,
     },
 };
@@ -23,41 +33,255 @@ pub fn try_create_countersigned_game_result<S: EloRatingSystem>(
     send_publish_game_result_request::<S>(new_game_result)
 }
 
+/**
+ * If the opponent has to approve the game result it's proposed to them instead, and the returned hash
+ * is the one it will have once they accept it
+ */
 pub fn send_publish_game_result_request<S: EloRatingSystem>(
     new_game_result: GameResult,
 ) -> ExternResult<EntryHashB64> {
-    let preflight_request = build_game_result_preflight(&new_game_result)?;
+    if requires_manual_approval::<S>(&new_game_result)? {
+        return Ok(propose_game_result(&new_game_result)??);
+    }
 
-    let my_response = match accept_countersigning_preflight_request(preflight_request)? {
-        PreflightRequestAcceptance::Accepted(response) => Ok(response),
-        _ => Err(WasmError::Guest(
-            "There was an error when building the preflight_request for the publishing of game result".into(),
-        )),
-    }?;
+    let preflight_request = build_game_result_preflight(
+        &new_game_result,
+        S::countersigning_session_duration_in_millis(),
+    )?;
 
-    let opponent_address = new_game_result.opponent()?;
+    Ok(request_countersignature(
+        &new_game_result,
+        &preflight_request,
+        "request_publish_game_result",
+    )??)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum PublishPath {
+    Countersigned,
+    // The game result was proposed to the opponent, who will start a session to countersign it if their user accepts it
+    PendingApproval,
+    // The opponent couldn't be reached, so the game result was published unilaterally and flagged for them
+    Flagged,
+    // The session failed and nothing was published: my source chain stays locked by it until the given time,
+    // after which publishing can be retried
+    Locked { until: Timestamp },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PublishGameResultOutcome {
+    pub game_result_hash: EntryHashB64,
+    pub path: PublishPath,
+    // Why each of the failed countersigning attempts failed
+    pub countersigning_errors: Vec<CountersigningError>,
+}
+
+/**
+ * Tries to countersign the new game result with the opponent as many times as the EloRatingSystem allows,
+ * and falls back to publishing it unilaterally and flagging it for the opponent if they can't be reached
+ *
+ * If the opponent rejects the game result it's not published at all, and if they have to approve it it's proposed to them
+ *
+ * After the first attempt, a new one is only started if its whole session fits in the timeout
+ *
+ * A failed session leaves my source chain locked until the end of its window, and this doesn't wait for it:
+ * the outcome is Locked then, with the hash of the game result of the abandoned session
+ */
+pub fn publish_game_result_with_fallback<S: EloRatingSystem>(
+    new_game_result: GameResult,
+) -> ExternResult<PublishGameResultOutcome> {
+    let started_at = sys_time()?.as_micros();
+    let timeout_in_micros = S::countersigning_timeout_in_millis() as i64 * 1_000;
+    let session_duration_in_millis = S::countersigning_session_duration_in_millis();
+
+    let mut countersigning_errors: Vec<CountersigningError> = Vec::new();
+
+    // No session is started until the opponent has been reached, so if they can't be my source chain
+    // is not locked and the game result is flagged right away
+    let opponent_reached = if requires_manual_approval::<S>(&new_game_result)? {
+        match propose_game_result(&new_game_result)? {
+            Ok(game_result_hash) => {
+                return Ok(PublishGameResultOutcome {
+                    game_result_hash,
+                    path: PublishPath::PendingApproval,
+                    countersigning_errors,
+                })
+            }
+            Err(error @ CountersigningError::Rejected { .. }) => return Err(error.into()),
+            Err(error) => {
+                countersigning_errors.push(error);
+                false
+            }
+        }
+    } else {
+        match ask_opponent_to_check_game_result(&new_game_result)? {
+            Ok(()) => true,
+            Err(error @ CountersigningError::Rejected { .. }) => return Err(error.into()),
+            Err(error) => {
+                countersigning_errors.push(error);
+                false
+            }
+        }
+    };
+
+    if opponent_reached {
+        for attempt in 0..std::cmp::max(S::countersigning_attempts(), 1) {
+            let elapsed_in_micros = sys_time()?.as_micros() - started_at;
+            if attempt > 0
+                && elapsed_in_micros + session_duration_in_millis as i64 * 1_000 > timeout_in_micros
+            {
+                break;
+            }
+
+            let preflight_request =
+                build_game_result_preflight(&new_game_result, session_duration_in_millis)?;
+
+            match request_countersignature(
+                &new_game_result,
+                &preflight_request,
+                "request_publish_game_result",
+            )? {
+                Ok(game_result_hash) => {
+                    return Ok(PublishGameResultOutcome {
+                        game_result_hash,
+                        path: PublishPath::Countersigned,
+                        countersigning_errors,
+                    })
+                }
+                Err(error @ CountersigningError::Rejected { .. }) => return Err(error.into()),
+                Err(error) => match locked_until(&preflight_request, &error) {
+                    Some(until) => {
+                        countersigning_errors.push(error);
+
+                        return Ok(PublishGameResultOutcome {
+                            game_result_hash: preflight_request.app_entry_hash().clone().into(),
+                            path: PublishPath::Locked { until },
+                            countersigning_errors,
+                        });
+                    }
+                    // My source chain wasn't locked by this session, so I can go on with the next attempt
+                    // or the flagged game result
+                    None => countersigning_errors.push(error),
+                },
+            }
+        }
+    }
+
+    let game_result_hash = create_unilateral_game_result_and_flag::<S>(new_game_result)?;
+
+    Ok(PublishGameResultOutcome {
+        game_result_hash,
+        path: PublishPath::Flagged,
+        countersigning_errors,
+    })
+}
+
+/**
+ * Asks the opponent to check the game result before starting a session with them,
+ * so that my source chain is not locked if they can't be reached or would reject it
+ */
+fn ask_opponent_to_check_game_result(
+    game_result: &GameResult,
+) -> ExternResult<Result<(), CountersigningError>> {
+    let opponent_address = game_result.opponent()?;
 
     let call_remote_result = call_remote(
         AgentPubKey::from(opponent_address.clone()),
         zome_info()?.name,
-        FunctionName("request_publish_game_result".into()),
-        None,
-        my_response.clone(),
-    )?;
+        FunctionName("check_game_result".into()),
+        cap_secret_for(&opponent_address)?,
+        game_result.clone(),
+    );
 
     match call_remote_result {
-        ZomeCallResponse::Ok(response) => {
-            let counterparty_preflight_response: PreflightResponse = response.decode()?;
-            let game_result_hash = create_countersigned_game_result(
-                new_game_result.clone(),
-                vec![my_response, counterparty_preflight_response],
-            )?;
-
-            Ok(game_result_hash)
+        Ok(ZomeCallResponse::Ok(response)) => {
+            match response.decode::<Option<GameResultRejection>>()? {
+                None => Ok(Ok(())),
+                Some(rejection) => Ok(Err(CountersigningError::Rejected {
+                    agent: opponent_address,
+                    rejection,
+                })),
+            }
         }
-        _ => Err(WasmError::Guest(format!(
-            "There was an error calling the opponent's request_publish_game_result: {:?}",
-            call_remote_result
-        ))),
+        _ => Ok(Err(CountersigningError::OpponentUnreachable {
+            agent: opponent_address,
+            error: format!(
+                "There was an error calling the opponent's check_game_result: {:?}",
+                call_remote_result
+            ),
+        })),
     }
 }
+
+/**
+ * Runs one countersigning session with the opponent, calling the given function of theirs with my preflight response,
+ * and abandons it if it fails, without waiting for my source chain to be unlocked
+ */
+pub(crate) fn request_countersignature(
+    new_game_result: &GameResult,
+    preflight_request: &PreflightRequest,
+    remote_function: &str,
+) -> ExternResult<Result<EntryHashB64, CountersigningError>> {
+    let my_response = match accept_countersigning_preflight_request(preflight_request.clone()) {
+        Ok(PreflightRequestAcceptance::Accepted(response)) => response,
+        Ok(acceptance) => {
+            return Ok(Err(CountersigningError::PreflightNotAccepted {
+                error: format!("{:?}", acceptance),
+            }))
+        }
+        Err(error) => {
+            return Ok(Err(CountersigningError::PreflightNotAccepted {
+                error: format!("{:?}", error),
+            }))
+        }
+    };
+
+    let opponent_address = new_game_result.opponent()?;
+
+    let call_remote_result = call_remote(
+        AgentPubKey::from(opponent_address.clone()),
+        zome_info()?.name,
+        FunctionName(remote_function.into()),
+        cap_secret_for(&opponent_address)?,
+        my_response.clone(),
+    );
+
+    let result = match call_remote_result {
+        Ok(ZomeCallResponse::Ok(response)) => match response.decode()? {
+            PublishGameResultResponse::Accepted(_) if is_session_expired(preflight_request)? => {
+                Err(CountersigningError::SessionExpired)
+            }
+            PublishGameResultResponse::Accepted(counterparty_preflight_response) => {
+                match create_countersigned_game_result(
+                    new_game_result.clone(),
+                    vec![my_response, counterparty_preflight_response],
+                ) {
+                    Ok(game_result_hash) => Ok(game_result_hash),
+                    Err(_) if is_session_expired(preflight_request)? => {
+                        Err(CountersigningError::SessionExpired)
+                    }
+                    Err(error) => Err(CountersigningError::CommitFailed {
+                        error: format!("{:?}", error),
+                    }),
+                }
+            }
+            PublishGameResultResponse::Rejected(rejection) => Err(CountersigningError::Rejected {
+                agent: opponent_address,
+                rejection,
+            }),
+        },
+        _ => Err(CountersigningError::OpponentUnreachable {
+            agent: opponent_address,
+            error: format!(
+                "There was an error calling the opponent's {}: {:?}",
+                remote_function, call_remote_result
+            ),
+        }),
+    };
+
+    if let Err(error) = &result {
+        abandon_countersigning_session(preflight_request, error)?;
+    }
+
+    Ok(result)
+}
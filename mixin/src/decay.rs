@@ -0,0 +1,443 @@
+use hdk::prelude::holo_hash::*;
+use hdk::prelude::*;
+use std::collections::BTreeSet;
+
+use crate::{
+    category::{category_of, my_game_results_by_category},
+    elo_ranking::{add_to_ranking, get_top_ranked_agents, remove_from_ranking},
+    elo_rating::is_provisional,
+    game_result::{
+        handlers::{element_to_any_game_result, get_last_game_result_for_agents},
+        validation::is_valid,
+        AnyGameResult,
+    },
+    season::current_season,
+    EloRatingSystem, RatingOf, RatingSystem,
+};
+
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+/**
+ * Public record of the decay of the ranking of a player who hasn't published any game result for a while
+ *
+ * It's linked from the game result it decays, so that any agent can check why the player is ranked where they are,
+ * and it can be published by any agent
+ */
+#[hdk_entry(id = "inactivity_decay", required_validation_type = "full")]
+#[derive(Clone)]
+pub struct InactivityDecay {
+    // The decayed player
+    pub agent_pub_key: AgentPubKeyB64,
+    // The header with which the player published their last game result in this category
+    pub game_result_header_hash: HeaderHashB64,
+    // The head of the source chain of the player when the decay was created, from which the game result is their last one
+    pub agent_chain_top: HeaderHashB64,
+    // Days since the decay started, that is, past the inactivity_decay_after_days of the EloRatingSystem
+    pub decayed_days: u32,
+    // None if the player was dropped from the ranking
+    pub ranking_key: Option<i64>,
+}
+
+pub fn inactivity_decay_tag() -> LinkTag {
+    LinkTag::new("inactivity_decay")
+}
+
+// How many agents from the top of each ranking are checked for inactivity every time the scheduler runs
+const DECAY_SWEEP_SIZE: usize = 50;
+
+/**
+ * Decays the ranking of the agents at the top of the rankings I take part in, and my own,
+ * if they haven't published any game result for longer than the inactivity_decay_after_days of the EloRatingSystem
+ *
+ * Any agent can publish the decay of another one, since it's validated against the timestamp of their last game result:
+ * this way a player can't avoid it by staying offline
+ *
+ * Called periodically from the scheduler, only the rankings of the season that is being played are decayed
+ */
+pub fn apply_inactivity_decay<S: EloRatingSystem>() -> ExternResult<()> {
+    if S::inactivity_decay_after_days().is_none() {
+        return Ok(());
+    }
+    let my_pub_key = AgentPubKeyB64::from(agent_info()?.agent_latest_pubkey);
+    let season = current_season::<S>()?;
+
+    let mut categories: BTreeSet<Option<String>> =
+        my_game_results_by_category::<S>()?.into_keys().collect();
+    categories.insert(None);
+
+    for category in categories {
+        let mut agents = get_top_ranked_agents(&category, &season, DECAY_SWEEP_SIZE)?;
+        if !agents.contains(&my_pub_key) {
+            agents.push(my_pub_key.clone());
+        }
+
+        decay_ranking_of_agents::<S>(agents, &category, &season)?;
+    }
+
+    Ok(())
+}
+
+fn decay_ranking_of_agents<S: EloRatingSystem>(
+    agent_pub_keys: Vec<AgentPubKeyB64>,
+    category: &Option<String>,
+    season: &Option<String>,
+) -> ExternResult<()> {
+    let after_days = match S::inactivity_decay_after_days() {
+        Some(after_days) => after_days,
+        None => return Ok(()),
+    };
+    let now = sys_time()?;
+
+    for (agent_pub_key, last_game_result) in
+        get_last_game_result_for_agents(agent_pub_keys, category)?
+    {
+        // The header of the game result authored by the agent themselves
+        let (header, game_result) = match last_game_result {
+            Some(last_game_result) => last_game_result,
+            None => continue,
+        };
+        let decayed_days = decayed_days_between(header.as_content().timestamp(), now, after_days);
+
+        if decayed_days == 0 {
+            continue;
+        }
+
+        let game_result_hash = header
+            .as_content()
+            .entry_hash()
+            .ok_or(WasmError::Guest(
+                "This header doesn't contain any entry hash".into(),
+            ))?
+            .clone();
+        let elo_update = game_result
+            .elo_update_for(&agent_pub_key)
+            .ok_or(WasmError::Guest(
+                "This game result was not for this agent".into(),
+            ))?;
+
+        let provisional = is_provisional::<S>(elo_update.games_played);
+        if elo_update.season.ne(season)
+            || (provisional && S::hide_provisional_players_from_ranking())
+        {
+            continue;
+        }
+
+        let rating = elo_update.rating::<S::RatingSystem>()?;
+        let current_ranking_key = match last_inactivity_decay(&game_result_hash, &agent_pub_key)? {
+            Some(last_decay) if last_decay.decayed_days >= decayed_days => continue,
+            Some(last_decay) => last_decay.ranking_key,
+            None => Some(S::RatingSystem::ranking_key(&rating)),
+        };
+        let ranking_key = decayed_ranking_key::<S>(rating, decayed_days);
+
+        if ranking_key.eq(&current_ranking_key) {
+            continue;
+        }
+
+        let agent_chain_top = match chain_head_of(&agent_pub_key)? {
+            Some(agent_chain_top) => agent_chain_top,
+            None => continue,
+        };
+
+        let decay = InactivityDecay {
+            agent_pub_key: agent_pub_key.clone(),
+            game_result_header_hash: header.into_hash().into(),
+            agent_chain_top: agent_chain_top.into(),
+            decayed_days,
+            ranking_key,
+        };
+        create_entry(decay.clone())?;
+        create_link(
+            game_result_hash.clone(),
+            hash_entry(decay)?,
+            LinkType(0),
+            inactivity_decay_tag(),
+        )?;
+
+        if let Some(current_ranking_key) = current_ranking_key {
+            remove_from_ranking(
+                game_result_hash.clone(),
+                category,
+                season,
+                current_ranking_key,
+            )?;
+        }
+        if let Some(ranking_key) = ranking_key {
+            add_to_ranking::<S>(
+                game_result_hash,
+                agent_pub_key.into(),
+                category,
+                season,
+                ranking_key,
+                provisional,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+// The head of the source chain of the agent as I see it, or None if it's not valid
+fn chain_head_of(agent_pub_key: &AgentPubKeyB64) -> ExternResult<Option<HeaderHash>> {
+    let activity = get_agent_activity(
+        agent_pub_key.clone().into(),
+        ChainQueryFilter::new(),
+        ActivityRequest::Status,
+    )?;
+
+    match activity.status {
+        ChainStatus::Valid(chain_head) => Ok(Some(chain_head.hash)),
+        _ => Ok(None),
+    }
+}
+
+/**
+ * The key with which the agent is ranked for the given game result, taking into account its decay if they stopped playing
+ *
+ * Returns None if the agent was dropped from the ranking
+ */
+pub(crate) fn ranking_key_after_decay(
+    game_result_hash: &EntryHash,
+    agent_pub_key: &AgentPubKeyB64,
+    ranking_key: i64,
+) -> ExternResult<Option<i64>> {
+    match last_inactivity_decay(game_result_hash, agent_pub_key)? {
+        Some(last_decay) => Ok(last_decay.ranking_key),
+        None => Ok(Some(ranking_key)),
+    }
+}
+
+fn last_inactivity_decay(
+    game_result_hash: &EntryHash,
+    agent_pub_key: &AgentPubKeyB64,
+) -> ExternResult<Option<InactivityDecay>> {
+    let links = get_links(game_result_hash.clone(), Some(inactivity_decay_tag()))?;
+
+    let get_inputs = links
+        .into_iter()
+        .map(|link| GetInput::new(link.target.into(), GetOptions::default()))
+        .collect();
+
+    // The players of a countersigned game result share its entry, so its decays may belong to any of them
+    let last_decay = HDK
+        .with(|hdk| hdk.borrow().get(get_inputs))?
+        .into_iter()
+        .filter_map(|maybe_element| maybe_element)
+        .filter_map(|element| element.entry().to_app_option::<InactivityDecay>().ok()?)
+        .filter(|decay| decay.agent_pub_key.eq(agent_pub_key))
+        .max_by_key(|decay| decay.decayed_days);
+
+    Ok(last_decay)
+}
+
+fn decayed_days_between(last_game_at: Timestamp, now: Timestamp, after_days: u32) -> u32 {
+    let inactive_days = (now.as_micros() - last_game_at.as_micros()).max(0) / MICROS_PER_DAY;
+
+    (inactive_days as u32).saturating_sub(after_days)
+}
+
+fn decayed_ranking_key<S: EloRatingSystem>(rating: RatingOf<S>, decayed_days: u32) -> Option<i64> {
+    S::decayed_rating(rating, decayed_days).map(|rating| S::RatingSystem::ranking_key(&rating))
+}
+
+/**
+ * An inactivity decay can only be created for the last game result that the decayed player published in its category,
+ * after the days of inactivity have actually passed since then, and with the ranking key that the EloRatingSystem
+ * gives for those days
+ */
+pub fn validate_entry_inactivity_decay<S: EloRatingSystem>(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let decay = match validate_data
+        .element
+        .entry()
+        .to_app_option::<InactivityDecay>()?
+    {
+        Some(decay) => decay,
+        None => {
+            let entry_hash =
+                validate_data
+                    .element
+                    .header()
+                    .entry_hash()
+                    .ok_or(WasmError::Guest(
+                        "This header doesn't contain any entry hash".into(),
+                    ))?;
+            return Ok(ValidateCallbackResult::UnresolvedDependencies(vec![
+                entry_hash.clone().into(),
+            ]));
+        }
+    };
+
+    let after_days = match S::inactivity_decay_after_days() {
+        Some(after_days) => after_days,
+        None => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The inactivity decay is not enabled".into(),
+            ))
+        }
+    };
+
+    let game_result_element = must_get_valid_element(decay.game_result_header_hash.clone().into())?;
+
+    if !game_result_element
+        .header()
+        .author()
+        .eq(&AgentPubKey::from(decay.agent_pub_key.clone()))
+    {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the game results published by the decayed player can be decayed".into(),
+        ));
+    }
+
+    let last_game_result_result = validate_no_later_game_result::<S>(
+        &decay,
+        &game_result_element,
+        validate_data.element.header().timestamp(),
+    )?;
+    if !is_valid(&last_game_result_result) {
+        return Ok(last_game_result_result);
+    }
+
+    let elapsed_days = decayed_days_between(
+        game_result_element.header().timestamp(),
+        validate_data.element.header().timestamp(),
+        after_days,
+    );
+
+    if decay.decayed_days == 0 || decay.decayed_days > elapsed_days {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The decayed days don't match the time since the game result was published".into(),
+        ));
+    }
+
+    let (_, game_result) = element_to_any_game_result(game_result_element)?;
+
+    let elo_update = match game_result.elo_update_for(&decay.agent_pub_key) {
+        Some(elo_update) => elo_update,
+        None => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "Only the players of a game result can decay it".into(),
+            ))
+        }
+    };
+
+    let rating = elo_update.rating::<S::RatingSystem>()?;
+
+    if !decay
+        .ranking_key
+        .eq(&decayed_ranking_key::<S>(rating, decay.decayed_days))
+    {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The ranking key doesn't match the decayed rating".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/**
+ * Walks the source chain of the decayed player from the head referenced by the decay back to the decayed game result,
+ * which must be found, checking that they didn't publish any other game result in its category meanwhile
+ *
+ * The head can't be later than the decay itself, so the game result is the last one of the player when it was created
+ */
+fn validate_no_later_game_result<S: EloRatingSystem>(
+    decay: &InactivityDecay,
+    game_result_element: &Element,
+    decayed_at: Timestamp,
+) -> ExternResult<ValidateCallbackResult> {
+    let game_result_header_hash = HeaderHash::from(decay.game_result_header_hash.clone());
+    let agent_chain_top = HeaderHash::from(decay.agent_chain_top.clone());
+
+    let activity = must_get_agent_activity(
+        decay.agent_pub_key.clone().into(),
+        ChainFilter::new(agent_chain_top.clone()).until(game_result_header_hash.clone()),
+    )?;
+
+    let chain_top = activity
+        .iter()
+        .find(|activity| activity.header.header_address().eq(&agent_chain_top));
+
+    match chain_top {
+        Some(chain_top) if chain_top.header.header().timestamp() <= decayed_at => {}
+        _ => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The head of the source chain of the player can't be later than the decay".into(),
+            ))
+        }
+    }
+
+    if !activity.iter().any(|activity| {
+        activity
+            .header
+            .header_address()
+            .eq(&game_result_header_hash)
+    }) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The decayed game result is not in the source chain of the player".into(),
+        ));
+    }
+
+    let (_, game_result) = element_to_any_game_result(game_result_element.clone())?;
+    let category = category_of::<S>(&game_result)?;
+    let game_result_entry_types = AnyGameResult::entry_types()?;
+
+    for activity in activity.iter() {
+        let header = activity.header.header();
+
+        let is_later_game_result = !activity
+            .header
+            .header_address()
+            .eq(&game_result_header_hash)
+            && header
+                .entry_type()
+                .map(|entry_type| game_result_entry_types.contains(entry_type))
+                .unwrap_or(false);
+        if !is_later_game_result {
+            continue;
+        }
+
+        let later_element = must_get_valid_element(activity.header.header_address().clone())?;
+        let (_, later_game_result) = element_to_any_game_result(later_element)?;
+
+        if category_of::<S>(&later_game_result)?.eq(&category) {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The player published a later game result in the same category".into(),
+            ));
+        }
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/**
+ * An inactivity_decay link goes from the decayed game result to the decay
+ */
+pub(crate) fn validate_create_inactivity_decay_link(
+    link_add: &CreateLink,
+    target: &Entry,
+) -> ExternResult<ValidateCallbackResult> {
+    let decay = match InactivityDecay::try_from(target) {
+        Ok(decay) => decay,
+        Err(_) => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The target of an inactivity_decay link must be an inactivity decay".into(),
+            ))
+        }
+    };
+
+    let game_result_header = must_get_header(decay.game_result_header_hash.into())?;
+
+    if !game_result_header
+        .header()
+        .entry_hash()
+        .eq(&Some(&link_add.base_address))
+    {
+        return Ok(ValidateCallbackResult::Invalid(
+            "An inactivity decay can only be linked from the game result it decays".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
@@ -1,35 +1,300 @@
 use hc_lib_ranking_index::*;
 use hdk::prelude::holo_hash::AgentPubKeyB64;
 use hdk::prelude::*;
-use skill_rating::elo::EloRating;
+use std::cell::RefCell;
 use std::collections::BTreeMap;
 
-use crate::EloRatingSystem;
+use crate::{
+    elo_rating::get_elo_rating_for_agents_in_category, season::current_season, EloRatingSystem,
+    RatingSystem,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RankedAgent {
+    pub agent_pub_key: AgentPubKeyB64,
+    pub provisional: bool,
+}
 
-pub type EloRanking = BTreeMap<usize, Vec<AgentPubKeyB64>>;
+// Agents indexed by the ranking key of their rating
+pub type EloRanking = BTreeMap<i64, Vec<RankedAgent>>;
 
 pub const ELO_RANKING_INDEX: RankingIndex = RankingIndex {
     name: "elo_ranking",
     index_interval: 200,
 };
 
+/**
+ * The ranking index of the given category and season,
+ * or the ELO_RANKING_INDEX if the zome doesn't use categories nor seasons
+ *
+ * Each part of the name is marked and prefixed with its length, so that no two category and season pairs
+ * share the same index whatever characters they contain
+ */
+pub(crate) fn ranking_index_for(
+    category: &Option<String>,
+    season: &Option<String>,
+) -> RankingIndex {
+    if category.is_none() && season.is_none() {
+        return ELO_RANKING_INDEX;
+    }
+
+    let mut name = String::from(ELO_RANKING_INDEX.name);
+    if let Some(category) = category {
+        name.push_str(&format!(":c{}:{}", category.len(), category));
+    }
+    if let Some(season) = season {
+        name.push_str(&format!(":s{}:{}", season.len(), season));
+    }
+
+    RankingIndex {
+        name: static_ranking_index_name(name),
+        index_interval: ELO_RANKING_INDEX.index_interval,
+    }
+}
+
+thread_local! {
+    // The names of the ranking indexes built in this wasm instance
+    static RANKING_INDEX_NAMES: RefCell<BTreeMap<String, &'static str>> = RefCell::new(BTreeMap::new());
+}
+
+// The index needs a static name, so each name is leaked only the first time it's used
+fn static_ranking_index_name(name: String) -> &'static str {
+    RANKING_INDEX_NAMES.with(|names| {
+        let mut names = names.borrow_mut();
+
+        if let Some(static_name) = names.get(&name) {
+            return *static_name;
+        }
+
+        let static_name: &'static str = Box::leak(name.clone().into_boxed_str());
+        names.insert(name, static_name);
+
+        static_name
+    })
+}
+
+// Tag of the links in the ELO_RANKING_INDEX
+#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+struct RankingTag {
+    agent_pub_key: AgentPubKey,
+    provisional: bool,
+}
+
+impl RankingTag {
+    fn from_tag(tag: SerializedBytes) -> Result<Self, SerializedBytesError> {
+        match RankingTag::try_from(tag.clone()) {
+            Ok(ranking_tag) => Ok(ranking_tag),
+            // Rankings created before provisional ratings existed only contain the agent's public key
+            Err(_) => Ok(RankingTag {
+                agent_pub_key: AgentPubKey::try_from(tag)?,
+                provisional: false,
+            }),
+        }
+    }
+}
+
+// Order in which the ranking is walked
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RankingDirection {
+    // From the lowest ranking key up
+    Ascending,
+    // From the highest ranking key down
+    Descending,
+}
+
+impl From<RankingDirection> for GetRankingDirection {
+    fn from(direction: RankingDirection) -> Self {
+        match direction {
+            RankingDirection::Ascending => GetRankingDirection::Ascendent,
+            RankingDirection::Descending => GetRankingDirection::Descendent,
+        }
+    }
+}
+
 #[derive(Serialize, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetEloRankingChunkInput {
-    pub from_elo: Option<usize>,
+    pub from_elo: Option<i64>,
+    // Last agent of the previous chunk, with from_elo being their ranking key: the chunk starts right after them
+    pub from_agent: Option<AgentPubKeyB64>,
+    // Descending if not given
+    pub direction: Option<RankingDirection>,
+    pub category: Option<String>,
+    // The current season if not given
+    pub season: Option<String>,
     pub agent_count: usize,
 }
 
-pub fn get_elo_ranking_chunk(
-    from_elo: Option<usize>,
+// Position of the last agent of a chunk, to be passed as from_elo and from_agent to get the next one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EloRankingCursor {
+    pub from_elo: i64,
+    pub from_agent: AgentPubKeyB64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EloRankingChunk {
+    pub ranking: EloRanking,
+    pub has_more: bool,
+    // None if the chunk is empty
+    pub next_cursor: Option<EloRankingCursor>,
+}
+
+/**
+ * Get the next agent_count agents of the ranking in the given direction
+ *
+ * Agents with the same ranking key are ordered by their public key, so that a chunk can end in the middle
+ * of a group of tied agents and the next one continues right after its last agent
+ */
+pub fn get_elo_ranking_chunk<S: EloRatingSystem>(
+    input: GetEloRankingChunkInput,
+) -> ExternResult<EloRankingChunk> {
+    let season = match input.season {
+        Some(season) => Some(season),
+        None => current_season::<S>()?,
+    };
+
+    let (agents, has_more) = get_ranking_page(
+        &input.category,
+        &season,
+        input.direction.unwrap_or(RankingDirection::Descending),
+        input.from_elo,
+        input.from_agent,
+        input.agent_count,
+    )?;
+
+    let next_cursor = agents
+        .last()
+        .map(|(ranking_key, ranked_agent)| EloRankingCursor {
+            from_elo: *ranking_key,
+            from_agent: ranked_agent.agent_pub_key.clone(),
+        });
+
+    Ok(EloRankingChunk {
+        ranking: into_elo_ranking(agents),
+        has_more,
+        next_cursor,
+    })
+}
+
+/**
+ * The agent_count agents that come after the cursor in the given direction, in their ranking order,
+ * and whether there are more agents after them
+ *
+ * The ranking index can cut a group of tied agents in half, so the last ranking key of a full chunk
+ * is discarded and the chunk is fetched again bigger if that leaves too few agents
+ */
+fn get_ranking_page(
+    category: &Option<String>,
+    season: &Option<String>,
+    direction: RankingDirection,
+    from_elo: Option<i64>,
+    from_agent: Option<AgentPubKeyB64>,
     agent_count: usize,
-) -> ExternResult<EloRanking> {
-    let cursor = from_elo.map(|elo| GetRankingCursor {
-        from_ranking: elo as i64,
+) -> ExternResult<(Vec<(i64, RankedAgent)>, bool)> {
+    let cursor_agent = from_agent.map(|agent| AgentPubKey::from(agent));
+    let mut fetch_count = agent_count + 1;
+
+    loop {
+        let chunk = get_ranking_chunk(category, season, direction.into(), from_elo, fetch_count)?;
+
+        let fetched_count: usize = chunk.values().map(|agents| agents.len()).sum();
+        let maybe_cut = fetched_count >= fetch_count;
+        let furthest_key = match direction {
+            RankingDirection::Ascending => chunk.keys().last().cloned(),
+            RankingDirection::Descending => chunk.keys().next().cloned(),
+        };
+
+        let mut agents = in_ranking_order(direction, chunk);
+
+        if maybe_cut {
+            agents.retain(|(ranking_key, _)| Some(*ranking_key) != furthest_key);
+        }
+        if let (Some(from_elo), Some(cursor_agent)) = (from_elo, &cursor_agent) {
+            agents.retain(|(ranking_key, ranked_agent)| {
+                *ranking_key != from_elo
+                    || comes_after(direction, &ranked_agent.agent_pub_key, cursor_agent)
+            });
+        }
+
+        if agents.len() > agent_count || !maybe_cut {
+            let has_more = maybe_cut || agents.len() > agent_count;
+            agents.truncate(agent_count);
+
+            return Ok((agents, has_more));
+        }
+
+        fetch_count *= 2;
+    }
+}
+
+/**
+ * Flattens the ranking ordered by ranking key in the given direction,
+ * and by public key for agents with the same ranking key
+ */
+fn in_ranking_order(direction: RankingDirection, ranking: EloRanking) -> Vec<(i64, RankedAgent)> {
+    let mut agents: Vec<(i64, RankedAgent)> = ranking
+        .into_iter()
+        .flat_map(|(ranking_key, ranked_agents)| {
+            ranked_agents
+                .into_iter()
+                .map(move |ranked_agent| (ranking_key, ranked_agent))
+        })
+        .collect();
+
+    agents.sort_by(|(key_a, agent_a), (key_b, agent_b)| {
+        key_b.cmp(key_a).then_with(|| {
+            AgentPubKey::from(agent_a.agent_pub_key.clone())
+                .get_raw_39()
+                .cmp(AgentPubKey::from(agent_b.agent_pub_key.clone()).get_raw_39())
+        })
     });
 
-    let next_chunk = ELO_RANKING_INDEX.get_entry_ranking_chunk(
-        GetRankingDirection::Descendent,
+    if let RankingDirection::Ascending = direction {
+        agents.reverse();
+    }
+
+    agents
+}
+
+// Whether the agent comes after the cursor agent among the agents with their same ranking key
+fn comes_after(
+    direction: RankingDirection,
+    agent_pub_key: &AgentPubKeyB64,
+    cursor_agent: &AgentPubKey,
+) -> bool {
+    let agent_pub_key = AgentPubKey::from(agent_pub_key.clone());
+
+    match direction {
+        RankingDirection::Ascending => agent_pub_key.get_raw_39() < cursor_agent.get_raw_39(),
+        RankingDirection::Descending => agent_pub_key.get_raw_39() > cursor_agent.get_raw_39(),
+    }
+}
+
+fn into_elo_ranking(agents: Vec<(i64, RankedAgent)>) -> EloRanking {
+    let mut elo_ranking: EloRanking = BTreeMap::new();
+
+    for (ranking_key, ranked_agent) in agents {
+        elo_ranking
+            .entry(ranking_key)
+            .or_insert_with(Vec::new)
+            .push(ranked_agent);
+    }
+
+    elo_ranking
+}
+
+fn get_ranking_chunk(
+    category: &Option<String>,
+    season: &Option<String>,
+    direction: GetRankingDirection,
+    from_elo: Option<i64>,
+    agent_count: usize,
+) -> ExternResult<EloRanking> {
+    let cursor = from_elo.map(|elo| GetRankingCursor { from_ranking: elo });
+
+    let next_chunk = ranking_index_for(category, season).get_entry_ranking_chunk(
+        direction,
         agent_count,
         cursor,
     )?;
@@ -37,38 +302,288 @@ pub fn get_elo_ranking_chunk(
     let mut elo_ranking: EloRanking = BTreeMap::new();
 
     for (ranking, entries_with_that_ranking) in next_chunk {
-        let agent_pub_keys = entries_with_that_ranking
+        let ranking_tags = entries_with_that_ranking
             .into_iter()
             .filter_map(|e| e.tag)
-            .map(|tag| AgentPubKey::try_from(tag))
-            .collect::<Result<Vec<AgentPubKey>, SerializedBytesError>>()?;
+            .map(|tag| RankingTag::from_tag(tag))
+            .collect::<Result<Vec<RankingTag>, SerializedBytesError>>()?;
 
-        let pub_keys_b64 = agent_pub_keys
+        let ranked_agents = ranking_tags
             .into_iter()
-            .map(|pub_key| AgentPubKeyB64::from(pub_key))
+            .map(|ranking_tag| RankedAgent {
+                agent_pub_key: AgentPubKeyB64::from(ranking_tag.agent_pub_key),
+                provisional: ranking_tag.provisional,
+            })
             .collect();
 
-        elo_ranking.insert(ranking as usize, pub_keys_b64);
+        elo_ranking.insert(ranking, ranked_agents);
     }
 
     Ok(elo_ranking)
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AgentRankPosition {
+    pub agent_pub_key: AgentPubKeyB64,
+    pub ranking_key: i64,
+    // 1 for the agents with the highest ranking key, agents with the same ranking key share their position
+    pub position: usize,
+    pub total_agents: usize,
+    // Percentage of the other agents in the ranking that have a lower ranking key
+    pub percentile: f32,
+}
+
+#[derive(Serialize, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAgentRankPositionInput {
+    pub agent_pub_key: AgentPubKeyB64,
+    pub category: Option<String>,
+}
+
+/**
+ * The position of the agent in the ranking of the given category, e.g. "#137 of 2450"
+ *
+ * This needs to walk the whole ranking, so it's meant to be called sparingly
+ */
+pub fn get_agent_rank_position<S: EloRatingSystem>(
+    agent_pub_key: AgentPubKeyB64,
+    category: Option<String>,
+) -> ExternResult<AgentRankPosition> {
+    let ranking_key = current_ranking_key::<S>(&agent_pub_key, &category)?;
+    let season = current_season::<S>()?;
+
+    let mut above = 0;
+    let mut below = 0;
+    let mut others = 0;
+
+    for (key, ranked_agents) in get_whole_ranking(&category, &season)? {
+        let others_with_key = ranked_agents
+            .iter()
+            .filter(|ranked_agent| !ranked_agent.agent_pub_key.eq(&agent_pub_key))
+            .count();
+
+        others += others_with_key;
+        if key > ranking_key {
+            above += others_with_key;
+        } else if key < ranking_key {
+            below += others_with_key;
+        }
+    }
+
+    Ok(AgentRankPosition {
+        agent_pub_key,
+        ranking_key,
+        position: above + 1,
+        // The agent may be hidden from the ranking, e.g. if their rating is still provisional
+        total_agents: others + 1,
+        percentile: match others {
+            0 => 100.0,
+            _ => below as f32 * 100.0 / others as f32,
+        },
+    })
+}
+
+#[derive(Serialize, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetRankingNeighbourhoodInput {
+    pub agent_pub_key: AgentPubKeyB64,
+    pub agent_count: usize,
+    pub category: Option<String>,
+}
+
+/**
+ * The agent_count agents ranked right above and right below the given agent in the given category,
+ * and the agent themselves if they are not hidden from the ranking
+ */
+pub fn get_ranking_neighbourhood<S: EloRatingSystem>(
+    agent_pub_key: AgentPubKeyB64,
+    agent_count: usize,
+    category: Option<String>,
+) -> ExternResult<EloRanking> {
+    let ratings =
+        get_elo_rating_for_agents_in_category::<S>(vec![agent_pub_key.clone()], &category)?;
+    let agent_rating = ratings.get(&agent_pub_key).ok_or(WasmError::Guest(
+        "Unreachable: error when getting the rating of the agent".into(),
+    ))?;
+    let ranking_key = S::RatingSystem::ranking_key(&agent_rating.rating);
+    let season = current_season::<S>()?;
+
+    let (mut neighbourhood, _) = get_ranking_page(
+        &category,
+        &season,
+        RankingDirection::Ascending,
+        Some(ranking_key),
+        Some(agent_pub_key.clone()),
+        agent_count,
+    )?;
+    let (mut below, _) = get_ranking_page(
+        &category,
+        &season,
+        RankingDirection::Descending,
+        Some(ranking_key),
+        Some(agent_pub_key.clone()),
+        agent_count,
+    )?;
+
+    if !(agent_rating.provisional && S::hide_provisional_players_from_ranking()) {
+        neighbourhood.push((
+            ranking_key,
+            RankedAgent {
+                agent_pub_key,
+                provisional: agent_rating.provisional,
+            },
+        ));
+    }
+    neighbourhood.append(&mut below);
+
+    Ok(into_elo_ranking(in_ranking_order(
+        RankingDirection::Descending,
+        into_elo_ranking(neighbourhood),
+    )))
+}
+
+fn current_ranking_key<S: EloRatingSystem>(
+    agent_pub_key: &AgentPubKeyB64,
+    category: &Option<String>,
+) -> ExternResult<i64> {
+    let ratings =
+        get_elo_rating_for_agents_in_category::<S>(vec![agent_pub_key.clone()], category)?;
+
+    let agent_rating = ratings.get(agent_pub_key).ok_or(WasmError::Guest(
+        "Unreachable: error when getting the rating of the agent".into(),
+    ))?;
+
+    Ok(S::RatingSystem::ranking_key(&agent_rating.rating))
+}
+
+/**
+ * The agent_count agents at the top of the ranking of the given category and season
+ */
+pub(crate) fn get_top_ranked_agents(
+    category: &Option<String>,
+    season: &Option<String>,
+    agent_count: usize,
+) -> ExternResult<Vec<AgentPubKeyB64>> {
+    let (top, _) = get_ranking_page(
+        category,
+        season,
+        RankingDirection::Descending,
+        None,
+        None,
+        agent_count,
+    )?;
+
+    Ok(top
+        .into_iter()
+        .map(|(_, ranked_agent)| ranked_agent.agent_pub_key)
+        .collect())
+}
+
+// Size of the chunks in which the whole ranking is fetched
+const RANKING_PAGE_SIZE: usize = 200;
+
+/**
+ * All the agents in the ranking of the given category and season, fetched in pages from the highest ranking key down
+ */
+pub(crate) fn get_whole_ranking(
+    category: &Option<String>,
+    season: &Option<String>,
+) -> ExternResult<EloRanking> {
+    let mut whole_ranking: Vec<(i64, RankedAgent)> = vec![];
+    let mut cursor: Option<(i64, AgentPubKeyB64)> = None;
+
+    loop {
+        let (mut page, has_more) = get_ranking_page(
+            category,
+            season,
+            RankingDirection::Descending,
+            cursor.as_ref().map(|(from_elo, _)| *from_elo),
+            cursor.map(|(_, from_agent)| from_agent),
+            RANKING_PAGE_SIZE,
+        )?;
+
+        cursor = page
+            .last()
+            .map(|(ranking_key, ranked_agent)| (*ranking_key, ranked_agent.agent_pub_key.clone()));
+        whole_ranking.append(&mut page);
+
+        if !has_more || cursor.is_none() {
+            break;
+        }
+    }
+
+    Ok(into_elo_ranking(whole_ranking))
+}
+
+/**
+ * Puts the agent in the ranking of the given category and season, replacing their previous ranking in that same one
+ *
+ * previous_ranking_key must be None for the first game of the agent in the season:
+ * their ranking in the previous season is kept as its final standing
+ */
 pub fn put_elo_rating_in_ranking<S: EloRatingSystem>(
     game_result_hash: EntryHash,
     agent_pub_key: AgentPubKey,
-    previous_rating: Option<(EntryHash, EloRating)>,
-    new_rating: EloRating,
+    category: &Option<String>,
+    season: &Option<String>,
+    previous_ranking_key: Option<(EntryHash, i64)>,
+    new_ranking_key: i64,
+    provisional: bool,
+) -> ExternResult<()> {
+    let ranking_index = ranking_index_for(category, season);
+
+    if let Some((last_game_result_hash, previous_ranking_key)) = previous_ranking_key {
+        ranking_index.delete_entry_ranking(last_game_result_hash, previous_ranking_key)?;
+    } else if category.is_none() && season.is_none() {
+        // The initial rating of the agent is only ranked in the ELO_RANKING_INDEX, from init_elo
+        ELO_RANKING_INDEX.delete_entry_ranking(
+            agent_pub_key.clone().into(),
+            S::RatingSystem::ranking_key(&S::initial_rating()),
+        )?;
+    }
+
+    add_to_ranking::<S>(
+        game_result_hash,
+        agent_pub_key,
+        category,
+        season,
+        new_ranking_key,
+        provisional,
+    )
+}
+
+pub(crate) fn add_to_ranking<S: EloRatingSystem>(
+    game_result_hash: EntryHash,
+    agent_pub_key: AgentPubKey,
+    category: &Option<String>,
+    season: &Option<String>,
+    ranking_key: i64,
+    provisional: bool,
 ) -> ExternResult<()> {
-    if let Some((last_game_result_hash, previous_rating)) = previous_rating {
-        ELO_RANKING_INDEX.delete_entry_ranking(last_game_result_hash, previous_rating as i64)?;
-    } else {
-        ELO_RANKING_INDEX
-            .delete_entry_ranking(agent_pub_key.clone().into(), S::initial_rating() as i64)?;
+    if provisional && S::hide_provisional_players_from_ranking() {
+        return Ok(());
     }
 
-    let tag = SerializedBytes::try_from(agent_pub_key)?;
-    ELO_RANKING_INDEX.create_entry_ranking(game_result_hash, new_rating as i64, Some(tag))?;
+    let tag = SerializedBytes::try_from(RankingTag {
+        agent_pub_key,
+        provisional,
+    })?;
+    ranking_index_for(category, season).create_entry_ranking(
+        game_result_hash,
+        ranking_key,
+        Some(tag),
+    )?;
+
+    Ok(())
+}
+
+pub(crate) fn remove_from_ranking(
+    game_result_hash: EntryHash,
+    category: &Option<String>,
+    season: &Option<String>,
+    ranking_key: i64,
+) -> ExternResult<()> {
+    ranking_index_for(category, season).delete_entry_ranking(game_result_hash, ranking_key)?;
 
     Ok(())
 }
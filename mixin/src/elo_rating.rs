@@ -2,33 +2,130 @@ use std::collections::BTreeMap;
 
 use crate::elo_rating_system::EloRatingSystem;
 use crate::game_result::handlers::get_last_game_result_for_agents;
+use crate::rating_system::RatingSystem;
+use crate::season::current_season;
 pub use skill_rating::elo::{EloRating, DRAW, LOSS, WIN};
 
-use crate::game_result::GameResult;
+use crate::game_result::AnyGameResult;
 use ::hdk::prelude::holo_hash::AgentPubKeyB64;
 use ::hdk::prelude::*;
 
+// Rating type of the RatingSystem used by the given EloRatingSystem
+pub type RatingOf<S> = <<S as EloRatingSystem>::RatingSystem as RatingSystem>::Rating;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AgentRating<R> {
+    pub rating: R,
+    pub games_played: u32,
+    // Whether the agent is still within their first provisional_games() games
+    pub provisional: bool,
+}
+
 pub fn get_elo_rating_for_agents<S: EloRatingSystem>(
     agent_pub_keys: Vec<AgentPubKeyB64>,
-) -> ExternResult<BTreeMap<AgentPubKeyB64, EloRating>> {
-    let last_result_by_agent = get_last_game_result_for_agents(agent_pub_keys)?;
+) -> ExternResult<BTreeMap<AgentPubKeyB64, AgentRating<RatingOf<S>>>> {
+    get_elo_rating_for_agents_in_category::<S>(agent_pub_keys, &None)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GetEloRatingForAgentsInCategoryInput {
+    pub agent_pub_keys: Vec<AgentPubKeyB64>,
+    pub category: Option<String>,
+}
+
+/**
+ * The ratings of the agents in the given category, computed only from their game results of that category
+ */
+pub fn get_elo_rating_for_agents_in_category<S: EloRatingSystem>(
+    agent_pub_keys: Vec<AgentPubKeyB64>,
+    category: &Option<String>,
+) -> ExternResult<BTreeMap<AgentPubKeyB64, AgentRating<RatingOf<S>>>> {
+    let last_result_by_agent = get_last_game_result_for_agents(agent_pub_keys, category)?;
+    let season = current_season::<S>()?;
 
     // For each agent, extract their ELO rating from their latest game result
-    let mut elo_ratings_by_agent: BTreeMap<AgentPubKeyB64, EloRating> = BTreeMap::new();
+    let mut elo_ratings_by_agent: BTreeMap<AgentPubKeyB64, AgentRating<RatingOf<S>>> =
+        BTreeMap::new();
 
     for (agent_pub_key, latest_game_result) in last_result_by_agent {
-        let elo_rating =
-            elo_rating_from_last_game_result::<S>(&agent_pub_key, &latest_game_result)?;
-        elo_ratings_by_agent.insert(agent_pub_key, elo_rating);
+        let history = player_history_from_last_game_result::<S>(
+            &agent_pub_key,
+            &latest_game_result,
+            &season,
+        )?;
+
+        elo_ratings_by_agent.insert(
+            agent_pub_key,
+            AgentRating {
+                provisional: history.is_provisional::<S>(),
+                games_played: history.games_played,
+                rating: history.current_rating,
+            },
+        );
     }
 
     Ok(elo_ratings_by_agent)
 }
 
-pub(crate) fn elo_rating_from_last_game_result<S: EloRatingSystem>(
+/**
+ * What we know about a player's past games before a new game, derived from their game result chain
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerHistory<R> {
+    // Number of games the player had played before this one
+    pub games_played: u32,
+    pub current_rating: R,
+    // Highest ranking key that the player has reached
+    pub peak_ranking_key: i64,
+    // Season of the new game, current_rating has already been soft reset if it's a new season for the player
+    pub season: Option<String>,
+}
+
+impl<R> PlayerHistory<R> {
+    pub fn is_provisional<S: EloRatingSystem>(&self) -> bool {
+        is_provisional::<S>(self.games_played)
+    }
+}
+
+pub(crate) fn is_provisional<S: EloRatingSystem>(games_played: u32) -> bool {
+    games_played < S::provisional_games()
+}
+
+/**
+ * FIDE rule: K is 40 for the first 30 games, 20 while the rating stays under 2400, and 10 once a player has reached 2400
+ */
+pub fn fide_k_factor<R>(player: &PlayerHistory<R>) -> u32 {
+    if player.games_played < 30 {
+        40
+    } else if player.peak_ranking_key < 2400 {
+        20
+    } else {
+        10
+    }
+}
+
+pub(crate) fn rating_from_last_game_result<S: EloRatingSystem>(
+    agent_pub_key: &AgentPubKeyB64,
+    last_game_result: &Option<(HeaderHashed, AnyGameResult)>,
+    season: &Option<String>,
+) -> ExternResult<RatingOf<S>> {
+    let history =
+        player_history_from_last_game_result::<S>(agent_pub_key, last_game_result, season)?;
+
+    Ok(history.current_rating)
+}
+
+/**
+ * The history of the player before a new game of the given season, from their last game result
+ *
+ * If their last game was played in another season, their rating is soft reset for the new one
+ */
+pub(crate) fn player_history_from_last_game_result<S: EloRatingSystem>(
     agent_pub_key: &AgentPubKeyB64,
-    last_game_result: &Option<(HeaderHashed, GameResult)>,
-) -> ExternResult<EloRating> {
+    last_game_result: &Option<(HeaderHashed, AnyGameResult)>,
+    season: &Option<String>,
+) -> ExternResult<PlayerHistory<RatingOf<S>>> {
     match last_game_result {
         Some(game_result) => {
             let elo_update =
@@ -40,8 +137,150 @@ pub(crate) fn elo_rating_from_last_game_result<S: EloRatingSystem>(
                         agent_pub_key
                     )))?;
 
-            Ok(elo_update.current_elo)
+            let rating = elo_update.rating::<S::RatingSystem>()?;
+            let current_rating = match season.is_some() && elo_update.season.ne(season) {
+                true => S::season_soft_reset(rating),
+                false => rating,
+            };
+
+            Ok(PlayerHistory {
+                games_played: elo_update.games_played,
+                current_rating,
+                peak_ranking_key: elo_update.peak_ranking_key,
+                season: season.clone(),
+            })
+        }
+        None => {
+            let initial_rating = S::initial_rating();
+
+            Ok(PlayerHistory {
+                games_played: 0,
+                peak_ranking_key: S::RatingSystem::ranking_key(&initial_rating),
+                current_rating: initial_rating,
+                season: season.clone(),
+            })
         }
-        None => Ok(S::initial_rating()),
+    }
+}
+
+/**
+ * Computes the new ratings for both players with the RatingSystem selected by the EloRatingSystem,
+ * using the K-factor that corresponds to each player's history
+ */
+pub(crate) fn rate_game<S: EloRatingSystem>(
+    player_a: &PlayerHistory<RatingOf<S>>,
+    player_b: &PlayerHistory<RatingOf<S>>,
+    score_player_a: f32,
+) -> (RatingOf<S>, RatingOf<S>) {
+    S::RatingSystem::rate_game(
+        &player_a.current_rating,
+        &player_b.current_rating,
+        score_player_a,
+        k_factor_against::<S>(player_a, &[player_b]),
+        k_factor_against::<S>(player_b, &[player_a]),
+    )
+}
+
+/**
+ * Computes the new ratings for all the players of a free-for-all game, in the same order as the given players,
+ * by decomposing it in one pairing for each pair of players
+ */
+pub(crate) fn rate_multiplayer_game<S: EloRatingSystem>(
+    players: &[PlayerHistory<RatingOf<S>>],
+    placements: &[u32],
+) -> Vec<RatingOf<S>> {
+    players
+        .iter()
+        .enumerate()
+        .map(|(index, player)| {
+            let opponents: Vec<(usize, &PlayerHistory<RatingOf<S>>)> = players
+                .iter()
+                .enumerate()
+                .filter(|(opponent_index, _)| *opponent_index != index)
+                .collect();
+
+            let pairings: Vec<(RatingOf<S>, f32)> = opponents
+                .iter()
+                .map(|(opponent_index, opponent)| {
+                    (
+                        opponent.current_rating.clone(),
+                        pairing_score(placements[index], placements[*opponent_index]),
+                    )
+                })
+                .collect();
+
+            let opponent_histories: Vec<&PlayerHistory<RatingOf<S>>> = opponents
+                .into_iter()
+                .map(|(_, opponent)| opponent)
+                .collect();
+
+            S::RatingSystem::rate_multiplayer_game(
+                &player.current_rating,
+                &pairings,
+                k_factor_against::<S>(player, &opponent_histories),
+            )
+        })
+        .collect()
+}
+
+/**
+ * Computes the new ratings for the members of both teams, in the same order as the given members,
+ * from the aggregate rating of each team
+ */
+pub(crate) fn rate_team_game<S: EloRatingSystem>(
+    team_a: &[PlayerHistory<RatingOf<S>>],
+    team_b: &[PlayerHistory<RatingOf<S>>],
+    score_team_a: f32,
+) -> (Vec<RatingOf<S>>, Vec<RatingOf<S>>) {
+    let with_k_factors = |team: &[PlayerHistory<RatingOf<S>>],
+                          opponents: &[PlayerHistory<RatingOf<S>>]| {
+        let opponents: Vec<&PlayerHistory<RatingOf<S>>> = opponents.iter().collect();
+
+        team.iter()
+            .map(|member| {
+                (
+                    member.current_rating.clone(),
+                    k_factor_against::<S>(member, &opponents),
+                )
+            })
+            .collect::<Vec<(RatingOf<S>, u32)>>()
+    };
+
+    S::RatingSystem::rate_team_game(
+        &with_k_factors(team_a, team_b),
+        &with_k_factors(team_b, team_a),
+        score_team_a,
+    )
+}
+
+// A lower placement is a better one
+fn pairing_score(placement: u32, opponent_placement: u32) -> f32 {
+    if placement < opponent_placement {
+        WIN
+    } else if placement == opponent_placement {
+        DRAW
+    } else {
+        LOSS
+    }
+}
+
+/**
+ * Provisional players move faster, while established players move less when they face a provisional one
+ */
+fn k_factor_against<S: EloRatingSystem>(
+    player: &PlayerHistory<RatingOf<S>>,
+    opponents: &[&PlayerHistory<RatingOf<S>>],
+) -> u32 {
+    let k_factor = S::k_factor(player);
+
+    if player.is_provisional::<S>() {
+        S::provisional_k_factor(k_factor)
+    } else if opponents
+        .iter()
+        .any(|opponent| opponent.is_provisional::<S>())
+    {
+        S::k_factor_against_provisional(k_factor)
+    } else {
+        k_factor
     }
 }
@@ -1,25 +1,177 @@
-use crate::{elo_rating::EloRating, game_result::GameResultInfo};
+use crate::{
+    elo_rating::{PlayerHistory, RatingOf},
+    game_result::{GameResultInfo, MultiplayerGameResultInfo, TeamGameResultInfo},
+    rating_system::RatingSystem,
+};
 use hdk::prelude::*;
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum FlaggedGameResultDecision {
+    Accept,
+    // The game result is disputed, and excluded from the ratings until the dispute is withdrawn
+    Contest(String),
+}
+
 pub trait EloRatingSystem {
     type GameInfo: TryFrom<SerializedBytes> + TryInto<SerializedBytes>;
+    // Algorithm used to compute the new ratings of the players after a game, e.g. Elo or Glicko2
+    type RatingSystem: RatingSystem;
+
+    // Unique identifier of the game, at most one game result can be published for each player of the game
+    fn game_id(game: &Self::GameInfo) -> String;
+
     // Initial rating for a player who hasn't played any games
-    fn initial_rating() -> EloRating {
-        1000
+    fn initial_rating() -> <Self::RatingSystem as RatingSystem>::Rating {
+        Self::RatingSystem::default_initial_rating()
     }
 
-    // This is the maximum gain or loss of ELO that a match can affect
-    fn k_factor() -> u32 {
+    // This is the maximum gain or loss of ELO that a match can affect for the given player,
+    // use fide_k_factor to apply the FIDE 40/20/10 rule
+    fn k_factor(_player: &PlayerHistory<RatingOf<Self>>) -> u32 {
         32
     }
 
-    // How long we are going to wait until retrying to publish the already finished game results
+    // Number of games during which the rating of a new player is provisional, 0 disables provisional ratings
+    fn provisional_games() -> u32 {
+        0
+    }
+
+    // K-factor for a player whose rating is provisional, so that it converges faster
+    fn provisional_k_factor(k_factor: u32) -> u32 {
+        k_factor * 2
+    }
+
+    // K-factor for an established player who played against a provisional one, so that it moves less
+    fn k_factor_against_provisional(k_factor: u32) -> u32 {
+        k_factor / 2
+    }
+
+    // Whether provisional players stay out of the ranking until their provisional period ends
+    fn hide_provisional_players_from_ranking() -> bool {
+        false
+    }
+
+    // Rating category of the game, e.g. "blitz" or "classical", or None if all the games are rated together:
+    // each category has its own chain of game results for each player, and its own ratings and ranking
+    fn category(_game: &Self::GameInfo) -> Option<String> {
+        None
+    }
+
+    // Season in which the game counts, e.g. "2026-Q4", or None if the zome doesn't split its ratings in seasons:
+    // each season has its own ranking, and a player's rating is soft reset in their first game of a new season
+    fn season(_game: &Self::GameInfo) -> Option<String> {
+        None
+    }
+
+    // Season that was being played at the given time: the ranking of the current season is the one returned by default,
+    // and a game result can only be published while its season is being played
+    fn season_at(_timestamp: Timestamp) -> Option<String> {
+        None
+    }
+
+    // Rating with which a player starts a new season, from the one they ended their previous season with,
+    // e.g. Self::RatingSystem::move_towards(&rating, &Self::initial_rating(), 0.5) to go halfway back
+    fn season_soft_reset(rating: RatingOf<Self>) -> RatingOf<Self> {
+        rating
+    }
+
+    // Days without publishing any game result after which the ranking of a player starts to decay,
+    // None disables the decay
+    fn inactivity_decay_after_days() -> Option<u32> {
+        None
+    }
+
+    // Rating with which a player is ranked once their decay has lasted decayed_days, or None to drop them
+    // from the ranking until they play again, e.g. Self::RatingSystem::move_towards(&rating, &floor, fraction)
+    //
+    // The decay only changes the ranking: the next game of the player is still rated from their last rating
+    fn decayed_rating(rating: RatingOf<Self>, _decayed_days: u32) -> Option<RatingOf<Self>> {
+        Some(rating)
+    }
+
+    // Base of the backoff between the attempts at resolving each game result flagged for me: a flag that failed
+    // is retried after this interval, and then exponentially less often, up to 64 times this interval
+    //
+    // This doesn't change the schedule, which runs every minute since its other tasks need it,
+    // so the retries happen at the first run after they are due
     fn unpublished_games_retry_interval_in_mins() -> u32 {
         1
     }
 
+    // How long the countersigning sessions last: the source chains of the players are locked meanwhile,
+    // also when the session fails, since a failed session is only abandoned at the end of its window
+    fn countersigning_session_duration_in_millis() -> u64 {
+        5_000
+    }
+
+    // Whether only the opponents to which I have granted access with grant_countersigning_capability
+    // can send me countersigning requests
+    fn countersigning_requires_grant() -> bool {
+        false
+    }
+
+    // How long the grants created with grant_countersigning_capability last if they are not used
+    fn countersigning_grant_ttl_in_mins() -> u32 {
+        24 * 60
+    }
+
+    // How many times publish_game_result tries to countersign a game result before flagging it instead
+    fn countersigning_attempts() -> u32 {
+        1
+    }
+
+    // After this long, publish_game_result stops trying to countersign and flags the game result instead
+    fn countersigning_timeout_in_millis() -> u64 {
+        30_000
+    }
+
+    // How long an unpublished game result flagged for me can wait to be resolved before it's discarded
+    fn unpublished_game_flags_ttl_in_mins() -> u32 {
+        7 * 24 * 60
+    }
+
     fn validate_game_result(
         game: Self::GameInfo,
         result: GameResultInfo,
     ) -> ExternResult<ValidateCallbackResult>;
+
+    // Whether countersigning requests for this game wait for the user to accept them,
+    // instead of being countersigned automatically
+    fn requires_manual_approval(_game: Self::GameInfo, _result: GameResultInfo) -> bool {
+        false
+    }
+
+    // How long a game result proposal waits for the user to accept it
+    fn game_result_proposals_ttl_in_mins() -> u32 {
+        24 * 60
+    }
+
+    // Decides whether to accept a game result that the opponent published without my countersignature,
+    // called from try_resolve_unpublished_game_results
+    fn decide_on_flagged_game_result(
+        _game: Self::GameInfo,
+        _result: GameResultInfo,
+    ) -> FlaggedGameResultDecision {
+        FlaggedGameResultDecision::Accept
+    }
+
+    // Multiplayer game results are rejected unless the zome overrides this
+    fn validate_multiplayer_game_result(
+        _game: Self::GameInfo,
+        _result: MultiplayerGameResultInfo,
+    ) -> ExternResult<ValidateCallbackResult> {
+        Ok(ValidateCallbackResult::Invalid(
+            "This zome does not accept multiplayer game results".into(),
+        ))
+    }
+
+    // Team game results are rejected unless the zome overrides this
+    fn validate_team_game_result(
+        _game: Self::GameInfo,
+        _result: TeamGameResultInfo,
+    ) -> ExternResult<ValidateCallbackResult> {
+        Ok(ValidateCallbackResult::Invalid(
+            "This zome does not accept team game results".into(),
+        ))
+    }
 }
@@ -0,0 +1,339 @@
+use hdk::prelude::holo_hash::*;
+use hdk::prelude::*;
+
+use crate::{
+    category::my_game_results_by_category,
+    decay::ranking_key_after_decay,
+    elo_ranking::{put_elo_rating_in_ranking, remove_from_ranking},
+    elo_rating::is_provisional,
+    elo_rating_system::EloRatingSystem,
+    rating_system::RatingSystem,
+    remote_signal::EloRemoteSignal,
+    season::current_season,
+};
+
+use super::{
+    handlers::{element_to_any_game_result, last_undisputed_game_result, my_game_result_elements},
+    unpublished::accept_flagged_game_result,
+    AnyGameResult, EloUpdate, GameResult, GameResultDispute,
+};
+
+pub fn dispute_acknowledged_tag() -> LinkTag {
+    LinkTag::new("dispute_acknowledged")
+}
+
+/**
+ * The dispute for a game result always has the same hash, so validation can check whether it exists
+ */
+pub(crate) fn dispute_hash_for(game_result_hash: &EntryHash) -> ExternResult<EntryHash> {
+    hash_entry(GameResultDispute {
+        game_result_hash: game_result_hash.clone().into(),
+    })
+}
+
+pub(crate) fn is_disputed(game_result_hash: &EntryHash) -> ExternResult<bool> {
+    Ok(are_disputed(&vec![game_result_hash.clone()])?
+        .into_iter()
+        .any(|disputed| disputed))
+}
+
+/**
+ * A game result is disputed while any of the players who disputed it hasn't deleted their dispute
+ */
+pub(crate) fn are_disputed(game_result_hashes: &Vec<EntryHash>) -> ExternResult<Vec<bool>> {
+    let get_inputs = game_result_hashes
+        .iter()
+        .map(|game_result_hash| {
+            Ok(GetInput::new(
+                dispute_hash_for(game_result_hash)?.into(),
+                GetOptions::default(),
+            ))
+        })
+        .collect::<ExternResult<Vec<GetInput>>>()?;
+
+    let all_details = HDK.with(|hdk| hdk.borrow().get_details(get_inputs))?;
+
+    Ok(all_details.into_iter().map(is_active_dispute).collect())
+}
+
+fn is_active_dispute(details: Option<Details>) -> bool {
+    match details {
+        Some(Details::Entry(entry_details)) => entry_details.headers.iter().any(|create| {
+            !entry_details
+                .deletes
+                .iter()
+                .any(|delete| delete.header().author().eq(create.header().author()))
+        }),
+        _ => false,
+    }
+}
+
+/**
+ * Disputes a game result that was published without my countersignature, and lets its author know
+ */
+pub(crate) fn dispute_game_result(
+    game_result: &GameResult,
+    game_result_hash: EntryHash,
+    reason: String,
+) -> ExternResult<()> {
+    let published_by_me = my_game_result_elements()?
+        .iter()
+        .any(|element| element.header().entry_hash().eq(&Some(&game_result_hash)));
+    if published_by_me {
+        return Err(WasmError::Guest(
+            "I can't dispute a game result that I published".into(),
+        ));
+    }
+
+    create_entry(GameResultDispute {
+        game_result_hash: game_result_hash.clone().into(),
+    })?;
+
+    let author = game_result.opponent()?;
+
+    remote_signal(
+        EloRemoteSignal::GameResultDisputed {
+            game_result_hash: game_result_hash.into(),
+            reason,
+        },
+        vec![author.into()],
+    )?;
+
+    Ok(())
+}
+
+/**
+ * Withdraws my dispute of the given game result, so that it counts again for the ratings of its players,
+ * and accepts it if it was flagged for me
+ */
+pub fn withdraw_game_result_dispute<S: EloRatingSystem>(
+    game_result_hash: EntryHashB64,
+) -> ExternResult<()> {
+    let game_result_hash = EntryHash::from(game_result_hash);
+    let my_pub_key = agent_info()?.agent_latest_pubkey;
+
+    let my_dispute =
+        match get_details(dispute_hash_for(&game_result_hash)?, GetOptions::default())? {
+            Some(Details::Entry(entry_details)) => entry_details
+                .headers
+                .into_iter()
+                .find(|create| create.header().author().eq(&my_pub_key)),
+            _ => None,
+        }
+        .ok_or(WasmError::Guest(
+            "I haven't disputed this game result".into(),
+        ))?;
+
+    delete_entry(my_dispute.header_address().clone())?;
+
+    accept_flagged_game_result::<S>(game_result_hash)
+}
+
+struct RankingEntry {
+    entry_hash: EntryHash,
+    // None if the agent was dropped from the ranking for inactivity
+    ranking_key: Option<i64>,
+    provisional: bool,
+    category: Option<String>,
+    season: Option<String>,
+}
+
+impl RankingEntry {
+    fn is_in_same_ranking(&self, other: &RankingEntry) -> bool {
+        self.category.eq(&other.category) && self.season.eq(&other.season)
+    }
+}
+
+/**
+ * While my latest game result of a category is disputed, I'm ranked by my latest undisputed one instead
+ *
+ * Called when I receive a dispute signal, and periodically in case I was offline when the dispute was created
+ */
+pub fn sync_my_ranking_with_disputes<S: EloRatingSystem>() -> ExternResult<()> {
+    for (category, my_game_results) in my_game_results_by_category::<S>()? {
+        sync_category_ranking_with_disputes::<S>(category, my_game_results)?;
+    }
+
+    Ok(())
+}
+
+fn sync_category_ranking_with_disputes<S: EloRatingSystem>(
+    category: Option<String>,
+    mut my_game_results: Vec<Element>,
+) -> ExternResult<()> {
+    let latest = match my_game_results.pop() {
+        Some(latest) => latest,
+        None => return Ok(()),
+    };
+    let latest_hash = latest
+        .header()
+        .entry_hash()
+        .ok_or(WasmError::Guest(
+            "This element doesn't have an entry hash".into(),
+        ))?
+        .clone();
+
+    let my_pub_key = AgentPubKeyB64::from(agent_info()?.agent_latest_pubkey);
+
+    let acknowledgements = get_links(latest_hash.clone(), Some(dispute_acknowledged_tag()))?;
+    let disputed = is_disputed(&latest_hash)?;
+
+    if disputed == !acknowledgements.is_empty() {
+        // The ranking is already up to date
+        return Ok(());
+    }
+
+    let latest_ranking = ranking_entry_for::<S>(&my_pub_key, &category, Some(latest))?;
+    let undisputed_ranking = ranking_entry_for::<S>(
+        &my_pub_key,
+        &category,
+        last_undisputed_game_result(my_game_results)?,
+    )?;
+
+    // The ranking of a past season is kept as it ended, only the ranking of the latest game result changes
+    let same_ranking = latest_ranking.is_in_same_ranking(&undisputed_ranking);
+
+    if disputed {
+        match same_ranking {
+            true => move_ranking::<S>(&my_pub_key, latest_ranking, undisputed_ranking)?,
+            false => remove_ranking_entry(latest_ranking)?,
+        }
+
+        create_link(
+            latest_hash.clone(),
+            dispute_hash_for(&latest_hash)?,
+            LinkType(0),
+            dispute_acknowledged_tag(),
+        )?;
+    } else {
+        match same_ranking {
+            true => move_ranking::<S>(&my_pub_key, undisputed_ranking, latest_ranking)?,
+            false => {
+                if let Some(ranking_key) = latest_ranking.ranking_key {
+                    put_elo_rating_in_ranking::<S>(
+                        latest_ranking.entry_hash,
+                        my_pub_key.clone().into(),
+                        &latest_ranking.category,
+                        &latest_ranking.season,
+                        None,
+                        ranking_key,
+                        latest_ranking.provisional,
+                    )?
+                }
+            }
+        }
+
+        for link in acknowledgements {
+            delete_link(link.create_link_hash)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Without any game result, the agent is ranked with their initial rating in the ELO_RANKING_INDEX
+fn ranking_entry_for<S: EloRatingSystem>(
+    agent_pub_key: &AgentPubKeyB64,
+    category: &Option<String>,
+    game_result_element: Option<Element>,
+) -> ExternResult<RankingEntry> {
+    match game_result_element {
+        // With no previous game result the agent has the initial rating, in the ranking of the current season
+        None => Ok(RankingEntry {
+            entry_hash: AgentPubKey::from(agent_pub_key.clone()).into(),
+            ranking_key: Some(S::RatingSystem::ranking_key(&S::initial_rating())),
+            provisional: is_provisional::<S>(0),
+            category: category.clone(),
+            season: current_season::<S>()?,
+        }),
+        Some(element) => {
+            let entry_hash = element
+                .header()
+                .entry_hash()
+                .ok_or(WasmError::Guest(
+                    "This element doesn't have an entry hash".into(),
+                ))?
+                .clone();
+            let (_, game_result) = element_to_any_game_result(element)?;
+            let elo_update = elo_update_of(&game_result, agent_pub_key)?;
+            let ranking_key =
+                S::RatingSystem::ranking_key(&elo_update.rating::<S::RatingSystem>()?);
+
+            Ok(RankingEntry {
+                ranking_key: ranking_key_after_decay(&entry_hash, agent_pub_key, ranking_key)?,
+                entry_hash,
+                provisional: is_provisional::<S>(elo_update.games_played),
+                category: category.clone(),
+                season: elo_update.season,
+            })
+        }
+    }
+}
+
+fn elo_update_of(
+    game_result: &AnyGameResult,
+    agent_pub_key: &AgentPubKeyB64,
+) -> ExternResult<EloUpdate> {
+    game_result
+        .elo_update_for(agent_pub_key)
+        .ok_or(WasmError::Guest(
+            "This game result was not for this agent".into(),
+        ))
+}
+
+fn move_ranking<S: EloRatingSystem>(
+    agent_pub_key: &AgentPubKeyB64,
+    from: RankingEntry,
+    to: RankingEntry,
+) -> ExternResult<()> {
+    let to_ranking_key = match to.ranking_key {
+        Some(ranking_key) => ranking_key,
+        None => return remove_ranking_entry(from),
+    };
+
+    put_elo_rating_in_ranking::<S>(
+        to.entry_hash,
+        agent_pub_key.clone().into(),
+        &to.category,
+        &to.season,
+        from.ranking_key
+            .map(|ranking_key| (from.entry_hash, ranking_key)),
+        to_ranking_key,
+        to.provisional,
+    )
+}
+
+fn remove_ranking_entry(ranking_entry: RankingEntry) -> ExternResult<()> {
+    match ranking_entry.ranking_key {
+        Some(ranking_key) => remove_from_ranking(
+            ranking_entry.entry_hash,
+            &ranking_entry.category,
+            &ranking_entry.season,
+            ranking_key,
+        ),
+        None => Ok(()),
+    }
+}
+
+/**
+ * Whether the given agent played the given game result that I published, so that they can dispute it
+ */
+pub(crate) fn is_player_of_my_game_result(
+    game_result_hash: &EntryHash,
+    agent: &AgentPubKeyB64,
+) -> ExternResult<bool> {
+    let my_pub_key = AgentPubKeyB64::from(agent_info()?.agent_latest_pubkey);
+    if agent.eq(&my_pub_key) {
+        return Ok(false);
+    }
+
+    for element in my_game_result_elements()? {
+        if element.header().entry_hash().eq(&Some(game_result_hash)) {
+            let (_, game_result) = element_to_any_game_result(element)?;
+
+            return Ok(game_result.elo_update_for(agent).is_some());
+        }
+    }
+
+    Ok(false)
+}
@@ -0,0 +1,132 @@
+use hdk::prelude::holo_hash::*;
+use hdk::prelude::*;
+
+use crate::elo_rating_system::EloRatingSystem;
+
+use super::{
+    handlers::{element_to_any_game_result, entry_to_any_game_result},
+    AnyGameResult,
+};
+
+pub fn game_id_tag() -> LinkTag {
+    LinkTag::new("game_id")
+}
+
+pub(crate) fn game_id_path(game_id: &String) -> Path {
+    Path::from(vec![
+        Component::from(String::from("game_ids")),
+        Component::from(game_id.clone()),
+    ])
+}
+
+/**
+ * Gets the game id from the serialized GameInfo, through the EloRatingSystem
+ */
+pub(crate) fn game_id_for<S: EloRatingSystem>(game_info: &SerializedBytes) -> ExternResult<String> {
+    let info = S::GameInfo::try_from(game_info.clone())
+        .or(Err(WasmError::Guest("Could not convert game info".into())))?;
+
+    Ok(S::game_id(&info))
+}
+
+pub(crate) fn index_game_id(game_id: &String, game_result_hash: &EntryHash) -> ExternResult<()> {
+    let path = game_id_path(game_id);
+    path.ensure()?;
+
+    create_link(
+        path.hash()?,
+        game_result_hash.clone(),
+        LinkType(0),
+        game_id_tag(),
+    )?;
+
+    Ok(())
+}
+
+/**
+ * Gets all the game results that have been published for the given game, one for each agent that published it
+ */
+pub fn get_game_results_for_game(
+    game_id: String,
+) -> ExternResult<Vec<(EntryHashB64, AnyGameResult)>> {
+    let links = get_links(game_id_path(&game_id).hash()?, Some(game_id_tag()))?;
+
+    let mut targets: Vec<EntryHash> = links.into_iter().map(|link| link.target).collect();
+    targets.sort();
+    targets.dedup();
+
+    let get_inputs = targets
+        .iter()
+        .map(|target| GetInput::new(target.clone().into(), GetOptions::default()))
+        .collect();
+    let maybe_elements = HDK.with(|hdk| hdk.borrow().get(get_inputs))?;
+
+    let mut game_results: Vec<(EntryHashB64, AnyGameResult)> = Vec::new();
+
+    for (target, maybe_element) in targets.into_iter().zip(maybe_elements.into_iter()) {
+        if let Some(element) = maybe_element {
+            let (_, game_result) = element_to_any_game_result(element)?;
+            game_results.push((target.into(), game_result));
+        }
+    }
+
+    Ok(game_results)
+}
+
+/**
+ * Whether I have already committed a game result for this game to my source chain
+ */
+pub(crate) fn have_i_published_game(game_id: &String) -> ExternResult<bool> {
+    Ok(my_game_result_for_game(game_id)?.is_some())
+}
+
+/**
+ * The hash of the game result for this game in my source chain, if I have committed one
+ */
+pub(crate) fn my_game_result_for_game(game_id: &String) -> ExternResult<Option<EntryHash>> {
+    for entry_type in AnyGameResult::entry_types()? {
+        let filter = ChainQueryFilter::new()
+            .entry_type(entry_type)
+            .include_entries(true);
+
+        for element in query(filter)? {
+            if let Some(entry) = element.entry().as_option() {
+                if entry_to_any_game_result(entry)?.game_id().eq(game_id) {
+                    return Ok(element.header().entry_hash().cloned());
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/**
+ * Fails if this game already has a result, either in the game id index or in my source chain
+ */
+pub(crate) fn ensure_game_not_published(game_id: &String) -> ExternResult<()> {
+    if have_i_published_game(game_id)? {
+        return Err(WasmError::Guest(format!(
+            "I have already published a game result for the game {}",
+            game_id
+        )));
+    }
+
+    if is_game_indexed(game_id)? {
+        return Err(WasmError::Guest(format!(
+            "A game result for the game {} has already been published",
+            game_id
+        )));
+    }
+
+    Ok(())
+}
+
+/**
+ * Whether any game result for this game has already been indexed
+ */
+pub(crate) fn is_game_indexed(game_id: &String) -> ExternResult<bool> {
+    let links = get_links(game_id_path(game_id).hash()?, Some(game_id_tag()))?;
+
+    Ok(links.len() > 0)
+}
@@ -2,17 +2,27 @@ use std::collections::BTreeMap;
 
 use hdk::prelude::holo_hash::*;
 use hdk::prelude::*;
-use skill_rating::elo::EloRating;
 
 use crate::{
-    elo_rating::elo_rating_from_last_game_result, elo_rating_system::EloRatingSystem,
-    game_result::EloSignal, put_elo_rating_in_ranking,
+    category::{category_for, category_of, elements_in_category},
+    decay::ranking_key_after_decay,
+    elo_rating::{is_provisional, player_history_from_last_game_result, rate_game},
+    elo_rating_system::EloRatingSystem,
+    game_result::EloSignal,
+    put_elo_rating_in_ranking,
+    rating_system::RatingSystem,
+    season::{ensure_season_is_open, season_for},
 };
 
-use super::{unpublished::unpublished_game_tag, EloUpdate, GameResult};
+use super::{
+    dispute::{are_disputed, is_disputed},
+    game_id::{ensure_game_not_published, game_id_for, index_game_id},
+    unpublished::unpublished_game_tag,
+    AnyGameResult, EloUpdate, GameResult, MultiplayerGameResult, TeamGameResult,
+};
 
 pub fn index_game_result_if_not_exists<S: EloRatingSystem>(
-    game_result: GameResult,
+    game_result: AnyGameResult,
     game_result_hash: EntryHash,
 ) -> ExternResult<()> {
     let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
@@ -21,6 +31,7 @@ pub fn index_game_result_if_not_exists<S: EloRatingSystem>(
         .elo_update_for(&AgentPubKeyB64::from(my_agent_pub_key))
         .ok_or(WasmError::Guest("Invalid game result".into()))?;
 
+    let category = category_of::<S>(&game_result)?;
     let player_entry_hash = EntryHash::from(AgentPubKey::from(elo_update.player_address.clone()));
     let links = get_links(player_entry_hash.clone(), game_results_tag().into())?;
 
@@ -37,21 +48,29 @@ pub fn index_game_result_if_not_exists<S: EloRatingSystem>(
             player_entry_hash.clone(),
             game_result_hash.clone(),
             LinkType(0),
-            game_results_tag().into(),
+            game_results_tag_for(&category),
             ChainTopOrdering::Relaxed,
         ))
     })?;
 
-    let previous_rating = get_previous_rating(
+    index_game_id(&game_result.game_id(), &game_result_hash)?;
+
+    let previous_ranking_key = get_previous_ranking_key::<S>(
         elo_update.player_address.clone(),
-        elo_update.previous_game_result,
+        elo_update.previous_game_result.clone(),
+        &elo_update.season,
     )?;
 
+    let new_ranking_key = S::RatingSystem::ranking_key(&elo_update.rating::<S::RatingSystem>()?);
+
     put_elo_rating_in_ranking::<S>(
         game_result_hash.clone(),
         elo_update.player_address.into(),
-        previous_rating,
-        elo_update.current_elo,
+        &category,
+        &elo_update.season,
+        previous_ranking_key,
+        new_ranking_key,
+        is_provisional::<S>(elo_update.games_played),
     )?;
 
     emit_signal(EloSignal::NewGameResult {
@@ -62,10 +81,13 @@ pub fn index_game_result_if_not_exists<S: EloRatingSystem>(
     Ok(())
 }
 
-fn get_previous_rating(
+// The ranking of the agent's previous game result, if it was played in the same season and they weren't dropped
+// from the ranking for inactivity
+fn get_previous_ranking_key<S: EloRatingSystem>(
     agent_pub_key: AgentPubKeyB64,
     previous_game_result_hash: Option<HeaderHashB64>,
-) -> ExternResult<Option<(EntryHash, EloRating)>> {
+    season: &Option<String>,
+) -> ExternResult<Option<(EntryHash, i64)>> {
     match previous_game_result_hash {
         None => Ok(None),
         Some(hash) => {
@@ -73,19 +95,31 @@ fn get_previous_rating(
                 WasmError::Guest("Couldn't get the previous game result".into()),
             )?;
 
-            let (_, game_result) = element_to_game_result(element.clone())?;
-            let elo_rating = game_result
-                .elo_update_for(&agent_pub_key)
-                .ok_or(WasmError::Guest(
-                    "This game result was not for this agent".into(),
-                ))?
-                .current_elo;
+            let (_, game_result) = element_to_any_game_result(element.clone())?;
+            let previous_elo_update =
+                game_result
+                    .elo_update_for(&agent_pub_key)
+                    .ok_or(WasmError::Guest(
+                        "This game result was not for this agent".into(),
+                    ))?;
+
+            if previous_elo_update.season.ne(season) {
+                return Ok(None);
+            }
+
+            let rating = previous_elo_update.rating::<S::RatingSystem>()?;
 
             let entry_hash = element.header().entry_hash().ok_or(WasmError::Guest(
                 "This element doesn't have an entry hash".into(),
             ))?;
 
-            Ok(Some((entry_hash.clone(), elo_rating)))
+            let ranking_key = ranking_key_after_decay(
+                entry_hash,
+                &agent_pub_key,
+                S::RatingSystem::ranking_key(&rating),
+            )?;
+
+            Ok(ranking_key.map(|ranking_key| (entry_hash.clone(), ranking_key)))
         }
     }
 }
@@ -106,7 +140,10 @@ pub(crate) fn create_unilateral_game_result_and_flag<S: EloRatingSystem>(
         unpublished_game_tag(),
     )?;
 
-    index_game_result_if_not_exists::<S>(game_result, game_result_hash.clone())?;
+    index_game_result_if_not_exists::<S>(
+        AnyGameResult::OneVsOne(game_result),
+        game_result_hash.clone(),
+    )?;
     let entry_hash: EntryHashB64 = game_result_hash.into();
 
     Ok(entry_hash)
@@ -140,9 +177,40 @@ pub(crate) fn create_countersigned_game_result(
     Ok(entry_hash)
 }
 
+/**
+ * Creates a countersigned game result with more than two players, either a multiplayer or a team one
+ */
+pub(crate) fn create_countersigned_any_game_result(
+    game_result: AnyGameResult,
+    responses: Vec<PreflightResponse>,
+) -> ExternResult<EntryHashB64> {
+    let entry = Entry::CounterSign(
+        Box::new(
+            CounterSigningSessionData::try_from_responses(responses).map_err(
+                |countersigning_error| WasmError::Guest(countersigning_error.to_string()),
+            )?,
+        ),
+        game_result.app_entry_bytes()?,
+    );
+
+    let game_result_hash = hash_entry(entry.clone())?;
+    HDK.with(|h| {
+        h.borrow().create(CreateInput::new(
+            game_result.entry_def_id(),
+            entry,
+            // Countersigned entries MUST have strict ordering.
+            ChainTopOrdering::Strict,
+        ))
+    })?;
+
+    let entry_hash = EntryHashB64::from(game_result_hash);
+
+    Ok(entry_hash)
+}
+
 pub fn get_game_results_for_agents(
     agent_pub_keys: Vec<AgentPubKeyB64>,
-) -> ExternResult<BTreeMap<AgentPubKeyB64, Vec<(HeaderHashed, GameResult)>>> {
+) -> ExternResult<BTreeMap<AgentPubKeyB64, Vec<(HeaderHashed, AnyGameResult)>>> {
     let game_results_links = get_game_results_links_for_agents(agent_pub_keys)?;
 
     get_game_results_from_links(game_results_links)
@@ -153,12 +221,17 @@ pub(crate) fn build_new_game_result<S: EloRatingSystem>(
     opponent_address: &AgentPubKeyB64,
     my_score: f32,
 ) -> ExternResult<GameResult> {
+    ensure_game_not_published(&game_id_for::<S>(&game_info)?)?;
+    ensure_season_is_open::<S>(&game_info)?;
+
     let agent_info = agent_info()?;
 
     let my_address = AgentPubKeyB64::from(agent_info.agent_latest_pubkey);
 
-    let results =
-        get_last_game_result_for_agents(vec![my_address.clone(), opponent_address.clone()])?;
+    let results = get_last_game_result_for_agents(
+        vec![my_address.clone(), opponent_address.clone()],
+        &category_for::<S>(&game_info)?,
+    )?;
 
     let my_previous_game_result = results.get(&my_address).ok_or(WasmError::Guest(
         "Unreachable: error when getting my previous game result".into(),
@@ -182,36 +255,36 @@ pub(crate) fn internal_build_new_game_result<S: EloRatingSystem>(
     player_a: &AgentPubKeyB64,
     player_b: &AgentPubKeyB64,
     score_player_a: f32,
-    my_previous_game_result: Option<(HeaderHashed, GameResult)>,
-    opponent_previous_game_result: Option<(HeaderHashed, GameResult)>,
+    my_previous_game_result: Option<(HeaderHashed, AnyGameResult)>,
+    opponent_previous_game_result: Option<(HeaderHashed, AnyGameResult)>,
 ) -> ExternResult<GameResult> {
-    let my_previous_elo =
-        elo_rating_from_last_game_result::<S>(player_a, &my_previous_game_result)?;
-    let opponent_previous_elo =
-        elo_rating_from_last_game_result::<S>(player_b, &opponent_previous_game_result)?;
-
-    let (my_new_elo, opponent_new_elo) = skill_rating::elo::game(
-        my_previous_elo,
-        opponent_previous_elo,
-        score_player_a,
-        S::k_factor(),
-        S::k_factor(),
-    );
+    let season = season_for::<S>(&game_info)?;
+    let my_history =
+        player_history_from_last_game_result::<S>(player_a, &my_previous_game_result, &season)?;
+    let opponent_history = player_history_from_last_game_result::<S>(
+        player_b,
+        &opponent_previous_game_result,
+        &season,
+    )?;
 
-    let player_a = EloUpdate {
-        player_address: player_a.clone(),
-        current_elo: my_new_elo,
-        previous_game_result: my_previous_game_result
-            .map(|(header, _)| HeaderHashB64::from(header.into_hash())),
-    };
-    let player_b = EloUpdate {
-        player_address: player_b.clone(),
-        current_elo: opponent_new_elo,
-        previous_game_result: opponent_previous_game_result
-            .map(|(header, _)| HeaderHashB64::from(header.into_hash())),
-    };
+    let (my_new_rating, opponent_new_rating) =
+        rate_game::<S>(&my_history, &opponent_history, score_player_a);
+
+    let player_a = EloUpdate::new::<S::RatingSystem>(
+        player_a.clone(),
+        &my_history,
+        &my_new_rating,
+        my_previous_game_result.map(|(header, _)| HeaderHashB64::from(header.into_hash())),
+    )?;
+    let player_b = EloUpdate::new::<S::RatingSystem>(
+        player_b.clone(),
+        &opponent_history,
+        &opponent_new_rating,
+        opponent_previous_game_result.map(|(header, _)| HeaderHashB64::from(header.into_hash())),
+    )?;
 
     let result = GameResult {
+        game_id: game_id_for::<S>(&game_info)?,
         game_info,
         player_a,
         player_b,
@@ -223,29 +296,70 @@ pub(crate) fn internal_build_new_game_result<S: EloRatingSystem>(
 
 /** Helper functions */
 
-pub(crate) fn get_my_last_game_result() -> ExternResult<Option<(HeaderHashed, GameResult)>> {
-    let agent_info = agent_info()?;
+/**
+ * My latest undisputed game result of the given category,
+ * read from my own source chain since the links to it may not have been gossiped yet
+ */
+pub(crate) fn get_my_last_game_result<S: EloRatingSystem>(
+    category: &Option<String>,
+) -> ExternResult<Option<(HeaderHashed, AnyGameResult)>> {
+    let my_game_results = elements_in_category::<S>(my_game_result_elements()?, category)?;
+
+    match last_undisputed_game_result(my_game_results)? {
+        Some(element) => Ok(Some(element_to_any_game_result(element)?)),
+        None => Ok(None),
+    }
+}
+
+/**
+ * All the game results in my source chain, in the order in which they were committed
+ */
+pub(crate) fn my_game_result_elements() -> ExternResult<Vec<Element>> {
+    let mut my_game_results: Vec<Element> = Vec::new();
+    for entry_type in AnyGameResult::entry_types()? {
+        let filter = ChainQueryFilter::new()
+            .entry_type(entry_type)
+            .include_entries(true);
+        my_game_results.append(&mut query(filter)?);
+    }
+
+    my_game_results.sort_by_key(|element| element.header().header_seq());
 
-    let game_results =
-        get_last_game_result_for_agents(vec![agent_info.agent_latest_pubkey.clone().into()])?;
+    Ok(my_game_results)
+}
+
+pub(crate) fn last_undisputed_game_result(
+    game_result_elements: Vec<Element>,
+) -> ExternResult<Option<Element>> {
+    for element in game_result_elements.into_iter().rev() {
+        let entry_hash = element.header().entry_hash().ok_or(WasmError::Guest(
+            "This element doesn't have an entry hash".into(),
+        ))?;
 
-    let my_actual_latest_game_result = game_results
-        .get(&AgentPubKeyB64::from(agent_info.agent_latest_pubkey))
-        .ok_or(WasmError::Guest("Unreachable".into()))?;
+        if !is_disputed(entry_hash)? {
+            return Ok(Some(element));
+        }
+    }
 
-    Ok(my_actual_latest_game_result.clone())
+    Ok(None)
 }
 
 pub(crate) fn get_last_game_result_for_agents(
     agent_pub_keys: Vec<AgentPubKeyB64>,
-) -> ExternResult<BTreeMap<AgentPubKeyB64, Option<(HeaderHashed, GameResult)>>> {
+    category: &Option<String>,
+) -> ExternResult<BTreeMap<AgentPubKeyB64, Option<(HeaderHashed, AnyGameResult)>>> {
     // Get the game results links for the agents
     let mut game_results_links_by_agent = get_game_results_links_for_agents(agent_pub_keys)?;
+    let category_tag = game_results_tag_for(category);
 
-    // We only care about the latest published game result for an agent
+    // We only care about the latest published game result for an agent in this category
     // since it will contain the latest ELO for that agent
     for (agent_pub_key, links) in game_results_links_by_agent.clone() {
-        let only_latest_link = match links.last() {
+        let only_latest_link = match links
+            .iter()
+            .filter(|link| link.tag.eq(&category_tag))
+            .last()
+        {
             Some(link) => vec![link.clone()],
             None => vec![],
         };
@@ -256,8 +370,10 @@ pub(crate) fn get_last_game_result_for_agents(
     // Actually get the latest game results indexed by agents
     let latest_game_results_by_agent = get_game_results_from_links(game_results_links_by_agent)?;
 
-    let mut latest_result_by_agent: BTreeMap<AgentPubKeyB64, Option<(HeaderHashed, GameResult)>> =
-        BTreeMap::new();
+    let mut latest_result_by_agent: BTreeMap<
+        AgentPubKeyB64,
+        Option<(HeaderHashed, AnyGameResult)>,
+    > = BTreeMap::new();
 
     for (agent_pub_key, game_results) in latest_game_results_by_agent {
         latest_result_by_agent.insert(agent_pub_key, game_results.into_iter().next());
@@ -270,6 +386,22 @@ pub fn game_results_tag() -> LinkTag {
     LinkTag::new("game_result")
 }
 
+/**
+ * Tag of the links from the players to their game results of the given category
+ *
+ * Links are fetched by tag prefix, so the links with game_results_tag() point to the game results of all categories
+ */
+pub fn game_results_tag_for(category: &Option<String>) -> LinkTag {
+    match category {
+        None => game_results_tag(),
+        Some(category) => LinkTag::new(format!("game_result:{}", category)),
+    }
+}
+
+pub(crate) fn is_game_results_tag(tag: &LinkTag) -> bool {
+    tag.0.starts_with(&game_results_tag().0)
+}
+
 pub(crate) fn get_game_results_links_for_agents(
     agent_pub_keys: Vec<AgentPubKeyB64>,
 ) -> ExternResult<BTreeMap<AgentPubKeyB64, Vec<Link>>> {
@@ -289,7 +421,7 @@ pub(crate) fn get_game_results_links_for_agents(
     for (index, pub_key) in agent_pub_keys.into_iter().enumerate() {
         let links_for_agent = results[index].clone();
 
-        let mut filtered = filter_links_to_same_entry(links_for_agent);
+        let mut filtered = filter_disputed_links(filter_links_to_same_entry(links_for_agent))?;
 
         filtered.sort_by(|link_a, link_b| link_a.timestamp.cmp(&link_b.timestamp));
 
@@ -311,10 +443,24 @@ fn filter_links_to_same_entry(links: Vec<Link>) -> Vec<Link> {
     links_by_target.into_values().collect()
 }
 
+// Disputed game results don't count for the ratings of their players
+fn filter_disputed_links(links: Vec<Link>) -> ExternResult<Vec<Link>> {
+    let targets: Vec<EntryHash> = links.iter().map(|link| link.target.clone()).collect();
+
+    let disputed = are_disputed(&targets)?;
+
+    Ok(links
+        .into_iter()
+        .zip(disputed.into_iter())
+        .filter(|(_, disputed)| !disputed)
+        .map(|(link, _)| link)
+        .collect())
+}
+
 pub(crate) fn get_game_results_from_links(
     game_results_links_by_agent: BTreeMap<AgentPubKeyB64, Vec<Link>>,
-) -> ExternResult<BTreeMap<AgentPubKeyB64, Vec<(HeaderHashed, GameResult)>>> {
-    let mut game_results: BTreeMap<AgentPubKeyB64, Vec<(HeaderHashed, GameResult)>> =
+) -> ExternResult<BTreeMap<AgentPubKeyB64, Vec<(HeaderHashed, AnyGameResult)>>> {
+    let mut game_results: BTreeMap<AgentPubKeyB64, Vec<(HeaderHashed, AnyGameResult)>> =
         BTreeMap::new();
 
     let game_results_input: Vec<GetInput> = game_results_links_by_agent
@@ -331,7 +477,7 @@ pub(crate) fn get_game_results_from_links(
     let mut index = 0;
 
     for (agent_pub_key, links) in game_results_links_by_agent.into_iter() {
-        let mut results_for_agent: Vec<(HeaderHashed, GameResult)> = Vec::new();
+        let mut results_for_agent: Vec<(HeaderHashed, AnyGameResult)> = Vec::new();
         for _ in links {
             if let Some(game_result_details) = all_game_results_elements[index].clone() {
                 if let Details::Entry(entry_details) = game_result_details {
@@ -342,7 +488,7 @@ pub(crate) fn get_game_results_from_links(
                     });
 
                     if let Some(header) = header_for_author {
-                        let game_result = entry_to_game_result(&entry_details.entry)?;
+                        let game_result = entry_to_any_game_result(&entry_details.entry)?;
 
                         results_for_agent.push((header.hashed.clone(), game_result));
                     }
@@ -379,3 +525,39 @@ pub fn entry_to_game_result(entry: &Entry) -> ExternResult<GameResult> {
 
     Ok(game_result)
 }
+
+pub fn element_to_any_game_result(element: Element) -> ExternResult<(HeaderHashed, AnyGameResult)> {
+    let entry = element
+        .entry()
+        .as_option()
+        .ok_or(WasmError::Guest("Malformed GameResults entry".into()))?;
+
+    let game_result = entry_to_any_game_result(entry)?;
+
+    Ok((element.header_hashed().clone(), game_result))
+}
+
+pub fn entry_to_any_game_result(entry: &Entry) -> ExternResult<AnyGameResult> {
+    let bytes = match entry.clone() {
+        Entry::App(bytes) => Ok(bytes.into_sb()),
+        Entry::CounterSign(_, bytes) => Ok(bytes.into_sb()),
+        _ => Err(WasmError::Guest("Malformed GameResults entry".into())),
+    }?;
+
+    bytes_to_any_game_result(bytes)
+}
+
+pub(crate) fn bytes_to_any_game_result(bytes: SerializedBytes) -> ExternResult<AnyGameResult> {
+    if let Ok(game_result) = GameResult::try_from(bytes.clone()) {
+        return Ok(AnyGameResult::OneVsOne(game_result));
+    }
+
+    if let Ok(game_result) = MultiplayerGameResult::try_from(bytes.clone()) {
+        return Ok(AnyGameResult::Multiplayer(game_result));
+    }
+
+    let game_result = TeamGameResult::try_from(bytes)
+        .or(Err(WasmError::Guest("Malformed GameResults entry".into())))?;
+
+    Ok(AnyGameResult::Team(game_result))
+}
@@ -0,0 +1,240 @@
+use hdk::prelude::holo_hash::*;
+use hdk::prelude::*;
+
+use crate::{
+    category::category_of,
+    decay::{inactivity_decay_tag, validate_create_inactivity_decay_link},
+    elo_rating_system::EloRatingSystem,
+};
+
+use super::{
+    dispute::dispute_acknowledged_tag,
+    game_id::{game_id_path, game_id_tag},
+    handlers::{entry_to_any_game_result, game_results_tag_for, is_game_results_tag},
+    unpublished::unpublished_game_tag,
+    AnyGameResult, GameResultDispute,
+};
+
+/**
+ * Validates the links created by the mixin, identified by their tag
+ *
+ * Links with any other tag, like the ones in the ranking index, are left to their own validation
+ */
+pub fn validate_create_link_game_result<S: EloRatingSystem>(
+    validate_data: ValidateCreateLinkData,
+) -> ExternResult<ValidateCallbackResult> {
+    let link_add = validate_data.link_add;
+
+    if is_game_results_tag(&link_add.tag) {
+        validate_create_game_result_link::<S>(&link_add, &validate_data.target)
+    } else if link_add.tag.eq(&unpublished_game_tag()) {
+        validate_create_unpublished_game_link(&link_add, &validate_data.target)
+    } else if link_add.tag.eq(&game_id_tag()) {
+        validate_create_game_id_link(&link_add, &validate_data.target)
+    } else if link_add.tag.eq(&dispute_acknowledged_tag()) {
+        validate_create_dispute_acknowledged_link(&link_add, &validate_data.target)
+    } else if link_add.tag.eq(&inactivity_decay_tag()) {
+        validate_create_inactivity_decay_link(&link_add, &validate_data.target)
+    } else {
+        Ok(ValidateCallbackResult::Valid)
+    }
+}
+
+/**
+ * A game_result, game_id, dispute_acknowledged or inactivity_decay link can only be deleted by the agent who created it,
+ * and an unpublished_game flag can only be deleted by the agent who was flagged
+ */
+pub fn validate_delete_link_game_result(
+    validate_data: ValidateDeleteLinkData,
+) -> ExternResult<ValidateCallbackResult> {
+    let delete_link = validate_data.delete_link;
+
+    let link_add_header = must_get_header(delete_link.link_add_address.clone())?;
+
+    let link_add = match link_add_header.header() {
+        Header::CreateLink(create_link) => create_link.clone(),
+        _ => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The deleted header is not a CreateLink".into(),
+            ))
+        }
+    };
+
+    if is_game_results_tag(&link_add.tag)
+        || link_add.tag.eq(&game_id_tag())
+        || link_add.tag.eq(&dispute_acknowledged_tag())
+        || link_add.tag.eq(&inactivity_decay_tag())
+    {
+        if !delete_link.author.eq(&link_add.author) {
+            return Ok(ValidateCallbackResult::Invalid(
+                "Only the player who created a game result link can delete it".into(),
+            ));
+        }
+    } else if link_add.tag.eq(&unpublished_game_tag()) {
+        if !EntryHash::from(delete_link.author.clone()).eq(&link_add.base_address) {
+            return Ok(ValidateCallbackResult::Invalid(
+                "Only the flagged agent can delete an unpublished_game flag".into(),
+            ));
+        }
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn validate_create_game_result_link<S: EloRatingSystem>(
+    link_add: &CreateLink,
+    target: &Entry,
+) -> ExternResult<ValidateCallbackResult> {
+    if !EntryHash::from(link_add.author.clone()).eq(&link_add.base_address) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "game_result links can only be created from the author's own agent".into(),
+        ));
+    }
+
+    let game_result = match target_game_result(target) {
+        Some(game_result) => game_result,
+        None => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The target of a game_result link must be a game result".into(),
+            ))
+        }
+    };
+
+    if !is_player(&game_result, &link_add.author) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the players of a game result can link to it".into(),
+        ));
+    }
+
+    let category = match category_of::<S>(&game_result) {
+        Ok(category) => category,
+        Err(_) => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The game info of the game result is not valid".into(),
+            ))
+        }
+    };
+
+    if !link_add.tag.eq(&game_results_tag_for(&category)) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "A game result can only be linked under the category of its game info".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn validate_create_unpublished_game_link(
+    link_add: &CreateLink,
+    target: &Entry,
+) -> ExternResult<ValidateCallbackResult> {
+    let game_result = match target_game_result(target) {
+        Some(AnyGameResult::OneVsOne(game_result)) => game_result,
+        _ => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The target of an unpublished_game flag must be a one vs one game result".into(),
+            ))
+        }
+    };
+
+    let author = AgentPubKeyB64::from(link_add.author.clone());
+    let (player_a, player_b) = game_result.agents();
+
+    let opponent = if author.eq(&player_a) {
+        player_b
+    } else if author.eq(&player_b) {
+        player_a
+    } else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only a player of the game can flag it as unpublished".into(),
+        ));
+    };
+
+    if !EntryHash::from(AgentPubKey::from(opponent)).eq(&link_add.base_address) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "An unpublished_game flag can only be attached to the opponent of its author".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn validate_create_game_id_link(
+    link_add: &CreateLink,
+    target: &Entry,
+) -> ExternResult<ValidateCallbackResult> {
+    let game_result = match target_game_result(target) {
+        Some(game_result) => game_result,
+        None => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The target of a game_id link must be a game result".into(),
+            ))
+        }
+    };
+
+    if !is_player(&game_result, &link_add.author) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the players of a game result can index it".into(),
+        ));
+    }
+
+    if !game_id_path(&game_result.game_id())
+        .hash()?
+        .eq(&link_add.base_address)
+    {
+        return Ok(ValidateCallbackResult::Invalid(
+            "A game result can only be indexed under its own game id".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn validate_create_dispute_acknowledged_link(
+    link_add: &CreateLink,
+    target: &Entry,
+) -> ExternResult<ValidateCallbackResult> {
+    let dispute = match GameResultDispute::try_from(target) {
+        Ok(dispute) => dispute,
+        Err(_) => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The target of a dispute_acknowledged link must be a game result dispute".into(),
+            ))
+        }
+    };
+
+    if !EntryHash::from(dispute.game_result_hash.clone()).eq(&link_add.base_address) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "A dispute can only be acknowledged from the game result it disputes".into(),
+        ));
+    }
+
+    let game_result_entry = must_get_entry(dispute.game_result_hash.into())?;
+
+    let game_result = match target_game_result(game_result_entry.as_content()) {
+        Some(game_result) => game_result,
+        None => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The base of a dispute_acknowledged link must be a game result".into(),
+            ))
+        }
+    };
+
+    if !is_player(&game_result, &link_add.author) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the players of a game result can acknowledge its dispute".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn target_game_result(target: &Entry) -> Option<AnyGameResult> {
+    entry_to_any_game_result(target).ok()
+}
+
+fn is_player(game_result: &AnyGameResult, agent: &AgentPubKey) -> bool {
+    game_result
+        .elo_update_for(&AgentPubKeyB64::from(agent.clone()))
+        .is_some()
+}
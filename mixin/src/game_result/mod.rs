@@ -1,14 +1,24 @@
 use hdk::prelude::holo_hash::*;
 use hdk::prelude::*;
-use skill_rating::elo::EloRating;
 
+use crate::countersigning::common::CountersigningError;
+use crate::elo_rating::PlayerHistory;
+use crate::rating_system::{rating_from_bytes, rating_to_bytes, RatingSystem};
+
+pub mod dispute;
+pub mod game_id;
 pub mod handlers;
+pub mod link_validation;
+pub mod multiplayer;
+pub mod team;
 pub mod unpublished;
 pub mod validation;
 
-#[hdk_entry(id = "game_result", required_validation_type = "sub_chain")]
+#[hdk_entry(id = "game_result", required_validation_type = "full")]
 #[derive(Clone, PartialEq, PartialOrd)]
 pub struct GameResult {
+    // Identifies the game that was played, as given by the EloRatingSystem
+    pub game_id: String,
     pub player_a: EloUpdate,
     pub player_b: EloUpdate,
     pub score_player_a: f32,
@@ -50,21 +60,263 @@ impl GameResult {
 
     pub fn entry_type() -> ExternResult<EntryType> {
         Ok(EntryType::App(AppEntryType::new(
-            EntryDefIndex::from(0),
+            entry_def_index!(GameResult)?,
             zome_info()?.id,
             EntryVisibility::Public,
         )))
     }
 }
 
+#[hdk_entry(id = "multiplayer_game_result", required_validation_type = "full")]
+#[derive(Clone, PartialEq, PartialOrd)]
+pub struct MultiplayerGameResult {
+    pub game_id: String,
+    pub players: Vec<EloUpdate>,
+    // Final placement of each player, in the same order as players: 1 is the winner, and equal placements are ties
+    pub placements: Vec<u32>,
+    pub game_info: SerializedBytes,
+}
+
+impl MultiplayerGameResult {
+    pub fn elo_update_for(&self, agent: &AgentPubKeyB64) -> Option<EloUpdate> {
+        self.players
+            .iter()
+            .find(|elo_update| elo_update.player_address.eq(agent))
+            .cloned()
+    }
+
+    pub fn agents(&self) -> Vec<AgentPubKeyB64> {
+        self.players
+            .iter()
+            .map(|elo_update| elo_update.player_address.clone())
+            .collect()
+    }
+
+    pub fn opponents(&self) -> ExternResult<Vec<AgentPubKeyB64>> {
+        let my_pub_key = AgentPubKeyB64::from(agent_info()?.agent_latest_pubkey);
+
+        let agents = self.agents();
+        if !agents.contains(&my_pub_key) {
+            return Err(WasmError::Guest(
+                "This MultiplayerGameResult does not have my agent pub key in it".into(),
+            ));
+        }
+
+        Ok(agents
+            .into_iter()
+            .filter(|agent| !agent.eq(&my_pub_key))
+            .collect())
+    }
+
+    pub fn entry_type() -> ExternResult<EntryType> {
+        Ok(EntryType::App(AppEntryType::new(
+            entry_def_index!(MultiplayerGameResult)?,
+            zome_info()?.id,
+            EntryVisibility::Public,
+        )))
+    }
+}
+
+#[hdk_entry(id = "team_game_result", required_validation_type = "full")]
+#[derive(Clone, PartialEq, PartialOrd)]
+pub struct TeamGameResult {
+    pub game_id: String,
+    pub team_a: Vec<EloUpdate>,
+    pub team_b: Vec<EloUpdate>,
+    pub score_team_a: f32,
+    pub game_info: SerializedBytes,
+}
+
+impl TeamGameResult {
+    pub fn elo_update_for(&self, agent: &AgentPubKeyB64) -> Option<EloUpdate> {
+        self.team_a
+            .iter()
+            .chain(self.team_b.iter())
+            .find(|elo_update| elo_update.player_address.eq(agent))
+            .cloned()
+    }
+
+    pub fn teams(&self) -> (Vec<AgentPubKeyB64>, Vec<AgentPubKeyB64>) {
+        (
+            self.team_a
+                .iter()
+                .map(|elo_update| elo_update.player_address.clone())
+                .collect(),
+            self.team_b
+                .iter()
+                .map(|elo_update| elo_update.player_address.clone())
+                .collect(),
+        )
+    }
+
+    pub fn agents(&self) -> Vec<AgentPubKeyB64> {
+        let (mut team_a, mut team_b) = self.teams();
+        team_a.append(&mut team_b);
+        team_a
+    }
+
+    pub fn opponents(&self) -> ExternResult<Vec<AgentPubKeyB64>> {
+        let my_pub_key = AgentPubKeyB64::from(agent_info()?.agent_latest_pubkey);
+
+        let agents = self.agents();
+        if !agents.contains(&my_pub_key) {
+            return Err(WasmError::Guest(
+                "This TeamGameResult does not have my agent pub key in it".into(),
+            ));
+        }
+
+        // Teammates also have to countersign the result, so they are returned here too
+        Ok(agents
+            .into_iter()
+            .filter(|agent| !agent.eq(&my_pub_key))
+            .collect())
+    }
+
+    pub fn entry_type() -> ExternResult<EntryType> {
+        Ok(EntryType::App(AppEntryType::new(
+            entry_def_index!(TeamGameResult)?,
+            zome_info()?.id,
+            EntryVisibility::Public,
+        )))
+    }
+}
+
+/**
+ * Any of the entries that update the ratings of their players
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum AnyGameResult {
+    OneVsOne(GameResult),
+    Multiplayer(MultiplayerGameResult),
+    Team(TeamGameResult),
+}
+
+impl AnyGameResult {
+    pub fn elo_update_for(&self, agent: &AgentPubKeyB64) -> Option<EloUpdate> {
+        match self {
+            AnyGameResult::OneVsOne(game_result) => game_result.elo_update_for(agent),
+            AnyGameResult::Multiplayer(game_result) => game_result.elo_update_for(agent),
+            AnyGameResult::Team(game_result) => game_result.elo_update_for(agent),
+        }
+    }
+
+    pub fn agents(&self) -> Vec<AgentPubKeyB64> {
+        match self {
+            AnyGameResult::OneVsOne(game_result) => {
+                let (player_a, player_b) = game_result.agents();
+                vec![player_a, player_b]
+            }
+            AnyGameResult::Multiplayer(game_result) => game_result.agents(),
+            AnyGameResult::Team(game_result) => game_result.agents(),
+        }
+    }
+
+    // All the other agents that need to countersign this game result
+    pub fn opponents(&self) -> ExternResult<Vec<AgentPubKeyB64>> {
+        match self {
+            AnyGameResult::OneVsOne(game_result) => Ok(vec![game_result.opponent()?]),
+            AnyGameResult::Multiplayer(game_result) => game_result.opponents(),
+            AnyGameResult::Team(game_result) => game_result.opponents(),
+        }
+    }
+
+    pub fn game_id(&self) -> String {
+        match self {
+            AnyGameResult::OneVsOne(game_result) => game_result.game_id.clone(),
+            AnyGameResult::Multiplayer(game_result) => game_result.game_id.clone(),
+            AnyGameResult::Team(game_result) => game_result.game_id.clone(),
+        }
+    }
+
+    pub fn game_info(&self) -> SerializedBytes {
+        match self {
+            AnyGameResult::OneVsOne(game_result) => game_result.game_info.clone(),
+            AnyGameResult::Multiplayer(game_result) => game_result.game_info.clone(),
+            AnyGameResult::Team(game_result) => game_result.game_info.clone(),
+        }
+    }
+
+    pub fn entry(&self) -> ExternResult<Entry> {
+        match self {
+            AnyGameResult::OneVsOne(game_result) => Entry::try_from(game_result.clone()),
+            AnyGameResult::Multiplayer(game_result) => Entry::try_from(game_result.clone()),
+            AnyGameResult::Team(game_result) => Entry::try_from(game_result.clone()),
+        }
+    }
+
+    pub fn entry_def_id(&self) -> EntryDefId {
+        match self {
+            AnyGameResult::OneVsOne(game_result) => game_result.into(),
+            AnyGameResult::Multiplayer(game_result) => game_result.into(),
+            AnyGameResult::Team(game_result) => game_result.into(),
+        }
+    }
+
+    pub fn app_entry_bytes(&self) -> ExternResult<AppEntryBytes> {
+        match self {
+            AnyGameResult::OneVsOne(game_result) => Ok(game_result.clone().try_into()?),
+            AnyGameResult::Multiplayer(game_result) => Ok(game_result.clone().try_into()?),
+            AnyGameResult::Team(game_result) => Ok(game_result.clone().try_into()?),
+        }
+    }
+
+    pub fn entry_type(&self) -> ExternResult<EntryType> {
+        match self {
+            AnyGameResult::OneVsOne(_) => GameResult::entry_type(),
+            AnyGameResult::Multiplayer(_) => MultiplayerGameResult::entry_type(),
+            AnyGameResult::Team(_) => TeamGameResult::entry_type(),
+        }
+    }
+
+    pub fn entry_types() -> ExternResult<Vec<EntryType>> {
+        Ok(vec![
+            GameResult::entry_type()?,
+            MultiplayerGameResult::entry_type()?,
+            TeamGameResult::entry_type()?,
+        ])
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd)]
 pub struct EloUpdate {
     pub player_address: AgentPubKeyB64,
-    pub current_elo: EloRating,
+    // Rating of the player after this game, as serialized by the RatingSystem
+    pub current_rating: SerializedBytes,
+    // Number of games the player has played, including this one
+    pub games_played: u32,
+    // Highest ranking key the player has reached, including this game
+    pub peak_ranking_key: i64,
+    // Season in which this game was played, None if the zome doesn't use seasons
+    pub season: Option<String>,
     // Will be None in the first GameResult entry for that player
     pub previous_game_result: Option<HeaderHashB64>,
 }
 
+impl EloUpdate {
+    pub fn new<R: RatingSystem>(
+        player_address: AgentPubKeyB64,
+        previous_history: &PlayerHistory<R::Rating>,
+        new_rating: &R::Rating,
+        previous_game_result: Option<HeaderHashB64>,
+    ) -> ExternResult<Self> {
+        Ok(EloUpdate {
+            player_address,
+            current_rating: rating_to_bytes::<R>(new_rating)?,
+            games_played: previous_history.games_played + 1,
+            peak_ranking_key: previous_history
+                .peak_ranking_key
+                .max(R::ranking_key(new_rating)),
+            season: previous_history.season.clone(),
+            previous_game_result,
+        })
+    }
+
+    pub fn rating<R: RatingSystem>(&self) -> ExternResult<R::Rating> {
+        rating_from_bytes::<R>(&self.current_rating)
+    }
+}
+
 pub struct GameResultInfo {
     pub player_a: AgentPubKeyB64,
     pub player_b: AgentPubKeyB64,
@@ -81,11 +333,113 @@ impl GameResultInfo {
     }
 }
 
+pub struct MultiplayerGameResultInfo {
+    pub players: Vec<AgentPubKeyB64>,
+    pub placements: Vec<u32>,
+}
+
+impl MultiplayerGameResultInfo {
+    pub fn new(game_result: &MultiplayerGameResult) -> Self {
+        MultiplayerGameResultInfo {
+            players: game_result.agents(),
+            placements: game_result.placements.clone(),
+        }
+    }
+}
+
+pub struct TeamGameResultInfo {
+    pub team_a: Vec<AgentPubKeyB64>,
+    pub team_b: Vec<AgentPubKeyB64>,
+    pub score_team_a: f32,
+}
+
+impl TeamGameResultInfo {
+    pub fn new(game_result: &TeamGameResult) -> Self {
+        let (team_a, team_b) = game_result.teams();
+        TeamGameResultInfo {
+            team_a,
+            team_b,
+            score_team_a: game_result.score_team_a,
+        }
+    }
+}
+
+/**
+ * Created by a player to contest a game result that was published without their countersignature
+ *
+ * It only references the game result, so that its hash can be computed from the game result hash
+ */
+#[hdk_entry(id = "game_result_dispute", required_validation_type = "full")]
+#[derive(Clone)]
+pub struct GameResultDispute {
+    pub game_result_hash: EntryHashB64,
+}
+
+/**
+ * Private record of a failed attempt at resolving an unpublished_game flag, used to back off from it
+ */
+#[hdk_entry(id = "unpublished_game_retry", visibility = "private")]
+#[derive(Clone)]
+pub struct UnpublishedGameRetry {
+    pub flag_link_hash: HeaderHashB64,
+    pub failed_attempts: u32,
+    pub failed_at: Timestamp,
+    pub error: String,
+}
+
+impl UnpublishedGameRetry {
+    pub fn entry_type() -> ExternResult<EntryType> {
+        Ok(EntryType::App(AppEntryType::new(
+            entry_def_index!(UnpublishedGameRetry)?,
+            zome_info()?.id,
+            EntryVisibility::Private,
+        )))
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum EloSignal {
     NewGameResult {
         entry_hash: EntryHashB64,
+        game_result: AnyGameResult,
+    },
+    GameResultDisputed {
+        game_result_hash: EntryHashB64,
+        reason: String,
+    },
+    UnpublishedGameResultFailed {
+        game_result_hash: EntryHashB64,
+        failed_attempts: u32,
+        error: String,
+    },
+    ResolveUnpublishedGameResultsFailed {
+        error: String,
+    },
+    GameResultProposed {
+        game_result_hash: EntryHashB64,
         game_result: GameResult,
+        expires_at: Timestamp,
+    },
+    GameResultProposalRejected {
+        game_result_hash: EntryHashB64,
+    },
+    QueuedGameResultFailed {
+        queued_game_result_hash: HeaderHashB64,
+        error: String,
+    },
+    ProcessPublicationQueueFailed {
+        error: String,
+    },
+    RevokeStaleCountersigningCapabilitiesFailed {
+        error: String,
+    },
+    ApplyInactivityDecayFailed {
+        error: String,
+    },
+    CountersigningSessionAbandoned {
+        game_result_hash: EntryHashB64,
+        unlocks_at: Timestamp,
+        error: CountersigningError,
     },
 }
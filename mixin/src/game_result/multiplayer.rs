@@ -0,0 +1,113 @@
+use std::collections::BTreeSet;
+
+use hdk::prelude::holo_hash::*;
+use hdk::prelude::*;
+
+use crate::{
+    category::category_for,
+    elo_rating::{player_history_from_last_game_result, rate_multiplayer_game},
+    elo_rating_system::EloRatingSystem,
+    season::{ensure_season_is_open, season_for},
+};
+
+use super::{
+    game_id::{ensure_game_not_published, game_id_for},
+    handlers::get_last_game_result_for_agents,
+    AnyGameResult, EloUpdate, MultiplayerGameResult,
+};
+
+pub(crate) fn build_new_multiplayer_game_result<S: EloRatingSystem>(
+    game_info: SerializedBytes,
+    players: Vec<AgentPubKeyB64>,
+    placements: Vec<u32>,
+) -> ExternResult<MultiplayerGameResult> {
+    let my_address = AgentPubKeyB64::from(agent_info()?.agent_latest_pubkey);
+
+    if !players.contains(&my_address) {
+        return Err(WasmError::Guest(
+            "Cannot build a multiplayer game result in which I'm not playing".into(),
+        ));
+    }
+
+    ensure_game_not_published(&game_id_for::<S>(&game_info)?)?;
+    ensure_season_is_open::<S>(&game_info)?;
+
+    let results =
+        get_last_game_result_for_agents(players.clone(), &category_for::<S>(&game_info)?)?;
+
+    let previous_game_results = players
+        .iter()
+        .map(|player| {
+            results.get(player).cloned().ok_or(WasmError::Guest(
+                "Unreachable: error when getting the previous game result of a player".into(),
+            ))
+        })
+        .collect::<ExternResult<Vec<Option<(HeaderHashed, AnyGameResult)>>>>()?;
+
+    internal_build_new_multiplayer_game_result::<S>(
+        game_info,
+        &players,
+        placements,
+        previous_game_results,
+    )
+}
+
+pub(crate) fn internal_build_new_multiplayer_game_result<S: EloRatingSystem>(
+    game_info: SerializedBytes,
+    players: &Vec<AgentPubKeyB64>,
+    placements: Vec<u32>,
+    previous_game_results: Vec<Option<(HeaderHashed, AnyGameResult)>>,
+) -> ExternResult<MultiplayerGameResult> {
+    if players.len() < 2 {
+        return Err(WasmError::Guest(
+            "A multiplayer game needs at least two players".into(),
+        ));
+    }
+
+    let different_players: BTreeSet<AgentPubKeyB64> = players.iter().cloned().collect();
+    if different_players.len() != players.len() {
+        return Err(WasmError::Guest(
+            "The same player cannot appear twice in a multiplayer game".into(),
+        ));
+    }
+
+    if placements.len() != players.len() || previous_game_results.len() != players.len() {
+        return Err(WasmError::Guest(
+            "There must be exactly one placement for each player".into(),
+        ));
+    }
+
+    let season = season_for::<S>(&game_info)?;
+    let histories = players
+        .iter()
+        .zip(previous_game_results.iter())
+        .map(|(player, previous_game_result)| {
+            player_history_from_last_game_result::<S>(player, previous_game_result, &season)
+        })
+        .collect::<ExternResult<Vec<_>>>()?;
+
+    let new_ratings = rate_multiplayer_game::<S>(&histories, &placements);
+
+    let mut elo_updates: Vec<EloUpdate> = Vec::new();
+
+    for (((player, history), new_rating), previous_game_result) in players
+        .iter()
+        .zip(histories.iter())
+        .zip(new_ratings.iter())
+        .zip(previous_game_results.into_iter())
+    {
+        elo_updates.push(EloUpdate::new::<S::RatingSystem>(
+            player.clone(),
+            history,
+            new_rating,
+            previous_game_result.map(|(header, _)| HeaderHashB64::from(header.into_hash())),
+        )?);
+    }
+
+    Ok(MultiplayerGameResult {
+        game_id: game_id_for::<S>(&game_info)?,
+        players: elo_updates,
+        placements,
+        game_info,
+    })
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeSet;
+
+use hdk::prelude::holo_hash::*;
+use hdk::prelude::*;
+
+use crate::{
+    category::category_for,
+    elo_rating::{player_history_from_last_game_result, rate_team_game, PlayerHistory, RatingOf},
+    elo_rating_system::EloRatingSystem,
+    season::{ensure_season_is_open, season_for},
+};
+
+use super::{
+    game_id::{ensure_game_not_published, game_id_for},
+    handlers::get_last_game_result_for_agents,
+    AnyGameResult, EloUpdate, TeamGameResult,
+};
+
+pub(crate) fn build_new_team_game_result<S: EloRatingSystem>(
+    game_info: SerializedBytes,
+    team_a: Vec<AgentPubKeyB64>,
+    team_b: Vec<AgentPubKeyB64>,
+    score_team_a: f32,
+) -> ExternResult<TeamGameResult> {
+    let my_address = AgentPubKeyB64::from(agent_info()?.agent_latest_pubkey);
+
+    if !team_a.contains(&my_address) && !team_b.contains(&my_address) {
+        return Err(WasmError::Guest(
+            "Cannot build a team game result in which I'm not playing".into(),
+        ));
+    }
+
+    ensure_game_not_published(&game_id_for::<S>(&game_info)?)?;
+    ensure_season_is_open::<S>(&game_info)?;
+
+    let mut players = team_a.clone();
+    players.append(&mut team_b.clone());
+
+    let results = get_last_game_result_for_agents(players, &category_for::<S>(&game_info)?)?;
+
+    let previous_game_results_for = |team: &Vec<AgentPubKeyB64>| {
+        team.iter()
+            .map(|player| {
+                results.get(player).cloned().ok_or(WasmError::Guest(
+                    "Unreachable: error when getting the previous game result of a player".into(),
+                ))
+            })
+            .collect::<ExternResult<Vec<Option<(HeaderHashed, AnyGameResult)>>>>()
+    };
+
+    let team_a_previous_game_results = previous_game_results_for(&team_a)?;
+    let team_b_previous_game_results = previous_game_results_for(&team_b)?;
+
+    internal_build_new_team_game_result::<S>(
+        game_info,
+        &team_a,
+        &team_b,
+        score_team_a,
+        team_a_previous_game_results,
+        team_b_previous_game_results,
+    )
+}
+
+pub(crate) fn internal_build_new_team_game_result<S: EloRatingSystem>(
+    game_info: SerializedBytes,
+    team_a: &Vec<AgentPubKeyB64>,
+    team_b: &Vec<AgentPubKeyB64>,
+    score_team_a: f32,
+    team_a_previous_game_results: Vec<Option<(HeaderHashed, AnyGameResult)>>,
+    team_b_previous_game_results: Vec<Option<(HeaderHashed, AnyGameResult)>>,
+) -> ExternResult<TeamGameResult> {
+    if team_a.is_empty() || team_b.is_empty() {
+        return Err(WasmError::Guest(
+            "Both teams need at least one player".into(),
+        ));
+    }
+
+    let different_players: BTreeSet<AgentPubKeyB64> =
+        team_a.iter().chain(team_b.iter()).cloned().collect();
+    if different_players.len() != team_a.len() + team_b.len() {
+        return Err(WasmError::Guest(
+            "The same player cannot appear twice in a team game".into(),
+        ));
+    }
+
+    if team_a_previous_game_results.len() != team_a.len()
+        || team_b_previous_game_results.len() != team_b.len()
+    {
+        return Err(WasmError::Guest(
+            "There must be exactly one previous game result for each player".into(),
+        ));
+    }
+
+    let season = season_for::<S>(&game_info)?;
+    let team_a_histories = team_histories::<S>(team_a, &team_a_previous_game_results, &season)?;
+    let team_b_histories = team_histories::<S>(team_b, &team_b_previous_game_results, &season)?;
+
+    let (team_a_new_ratings, team_b_new_ratings) =
+        rate_team_game::<S>(&team_a_histories, &team_b_histories, score_team_a);
+
+    Ok(TeamGameResult {
+        game_id: game_id_for::<S>(&game_info)?,
+        team_a: team_elo_updates::<S>(
+            team_a,
+            &team_a_histories,
+            &team_a_new_ratings,
+            team_a_previous_game_results,
+        )?,
+        team_b: team_elo_updates::<S>(
+            team_b,
+            &team_b_histories,
+            &team_b_new_ratings,
+            team_b_previous_game_results,
+        )?,
+        score_team_a,
+        game_info,
+    })
+}
+
+fn team_histories<S: EloRatingSystem>(
+    team: &Vec<AgentPubKeyB64>,
+    previous_game_results: &Vec<Option<(HeaderHashed, AnyGameResult)>>,
+    season: &Option<String>,
+) -> ExternResult<Vec<PlayerHistory<RatingOf<S>>>> {
+    team.iter()
+        .zip(previous_game_results.iter())
+        .map(|(player, previous_game_result)| {
+            player_history_from_last_game_result::<S>(player, previous_game_result, season)
+        })
+        .collect()
+}
+
+// Each member keeps their own chain of EloUpdates, pointing to their own previous game result
+fn team_elo_updates<S: EloRatingSystem>(
+    team: &Vec<AgentPubKeyB64>,
+    histories: &Vec<PlayerHistory<RatingOf<S>>>,
+    new_ratings: &Vec<RatingOf<S>>,
+    previous_game_results: Vec<Option<(HeaderHashed, AnyGameResult)>>,
+) -> ExternResult<Vec<EloUpdate>> {
+    team.iter()
+        .zip(histories.iter())
+        .zip(new_ratings.iter())
+        .zip(previous_game_results.into_iter())
+        .map(|(((player, history), new_rating), previous_game_result)| {
+            EloUpdate::new::<S::RatingSystem>(
+                player.clone(),
+                history,
+                new_rating,
+                previous_game_result.map(|(header, _)| HeaderHashB64::from(header.into_hash())),
+            )
+        })
+        .collect()
+}
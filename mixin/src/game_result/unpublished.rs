@@ -1,24 +1,175 @@
+use crate::block_list::is_blocked;
 use crate::game_result::GameResult;
 use crate::index_game_result_if_not_exists;
-use crate::{elo_rating::elo_rating_from_last_game_result, elo_rating_system::EloRatingSystem};
+use crate::{
+    category::category_for,
+    elo_rating::{player_history_from_last_game_result, rate_game},
+    elo_rating_system::{EloRatingSystem, FlaggedGameResultDecision},
+    season::season_for,
+};
 use hdk::prelude::holo_hash::*;
 use hdk::prelude::*;
+use std::collections::BTreeMap;
 
-use super::handlers::{element_to_game_result, get_my_last_game_result};
-use super::EloUpdate;
+use super::dispute::{dispute_game_result, is_disputed, sync_my_ranking_with_disputes};
+use super::game_id::have_i_published_game;
+use super::handlers::{
+    element_to_any_game_result, element_to_game_result, get_my_last_game_result,
+};
+use super::{AnyGameResult, EloSignal, EloUpdate, GameResultInfo, UnpublishedGameRetry};
 
 pub fn unpublished_game_tag() -> LinkTag {
     LinkTag::new("unpublished_game")
 }
 
 pub fn try_resolve_unpublished_game_results<S: EloRatingSystem>() -> ExternResult<()> {
+    clean_stale_unpublished_game_flags::<S>()?;
+
     let unpublished_game_results = get_my_unpublished_game_results()?;
+    let retries = get_my_unpublished_game_retries()?;
+    let now = sys_time()?.as_micros();
 
     for unpublished_game_result in unpublished_game_results {
-        create_game_result_and_resolve_flag::<S>(
-            unpublished_game_result.game_result,
-            unpublished_game_result.flag_link_hash,
-        )?;
+        let last_retry = retries.get(&HeaderHashB64::from(
+            unpublished_game_result.flag_link_hash.clone(),
+        ));
+
+        if let Some(retry) = last_retry {
+            if now < next_attempt_at::<S>(retry) {
+                continue;
+            }
+        }
+
+        let flag_link_hash = unpublished_game_result.flag_link_hash.clone();
+        let game_result_hash = unpublished_game_result.game_result_hash.clone();
+
+        if let Err(error) = resolve_unpublished_game_result::<S>(unpublished_game_result) {
+            let failed_attempts = last_retry.map(|retry| retry.failed_attempts).unwrap_or(0) + 1;
+
+            record_failed_attempt(flag_link_hash, game_result_hash, failed_attempts, error)?;
+        }
+    }
+
+    sync_my_ranking_with_disputes::<S>()?;
+
+    Ok(())
+}
+
+fn resolve_unpublished_game_result<S: EloRatingSystem>(
+    unpublished_game_result: UnpublishedGameResult,
+) -> ExternResult<()> {
+    if is_disputed(&unpublished_game_result.game_result_hash)? {
+        return Ok(());
+    }
+
+    match decide_on_flagged_game_result::<S>(&unpublished_game_result.game_result)? {
+        FlaggedGameResultDecision::Accept => {
+            create_game_result_and_resolve_flag::<S>(
+                unpublished_game_result.game_result,
+                unpublished_game_result.flag_link_hash,
+            )?;
+        }
+        FlaggedGameResultDecision::Contest(reason) => {
+            // The flag is kept so that the game result can be accepted if the dispute is withdrawn
+            dispute_game_result(
+                &unpublished_game_result.game_result,
+                unpublished_game_result.game_result_hash,
+                reason,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+// Flags that keep failing are retried exponentially less often, up to 64 times the retry interval
+fn next_attempt_at<S: EloRatingSystem>(retry: &UnpublishedGameRetry) -> i64 {
+    let interval_in_micros = S::unpublished_games_retry_interval_in_mins() as i64 * 60 * 1_000_000;
+    let backoff = 1_i64 << std::cmp::min(retry.failed_attempts.saturating_sub(1), 6);
+
+    retry.failed_at.as_micros() + interval_in_micros * backoff
+}
+
+/**
+ * The latest failed attempt at resolving each of the flags, read from my source chain
+ */
+fn get_my_unpublished_game_retries() -> ExternResult<BTreeMap<HeaderHashB64, UnpublishedGameRetry>>
+{
+    let filter = ChainQueryFilter::new()
+        .entry_type(UnpublishedGameRetry::entry_type()?)
+        .include_entries(true);
+
+    let mut retries: BTreeMap<HeaderHashB64, UnpublishedGameRetry> = BTreeMap::new();
+
+    // Elements are returned in chain order, so the latest retry for each flag wins
+    for element in query(filter)? {
+        if let Some(retry) = element.entry().to_app_option::<UnpublishedGameRetry>()? {
+            retries.insert(retry.flag_link_hash.clone(), retry);
+        }
+    }
+
+    Ok(retries)
+}
+
+/**
+ * Records the failed attempt in a private entry, and lets the UI know about it
+ */
+fn record_failed_attempt(
+    flag_link_hash: HeaderHash,
+    game_result_hash: EntryHash,
+    failed_attempts: u32,
+    error: WasmError,
+) -> ExternResult<()> {
+    let error = format!("{:?}", error);
+
+    create_entry(UnpublishedGameRetry {
+        flag_link_hash: flag_link_hash.into(),
+        failed_attempts,
+        failed_at: sys_time()?,
+        error: error.clone(),
+    })?;
+
+    emit_signal(EloSignal::UnpublishedGameResultFailed {
+        game_result_hash: game_result_hash.into(),
+        failed_attempts,
+        error,
+    })?;
+
+    Ok(())
+}
+
+fn decide_on_flagged_game_result<S: EloRatingSystem>(
+    game_result: &GameResult,
+) -> ExternResult<FlaggedGameResultDecision> {
+    let game_info = S::GameInfo::try_from(game_result.game_info.clone()).or(Err(
+        WasmError::Guest(String::from("Could not convert game info")),
+    ))?;
+
+    Ok(S::decide_on_flagged_game_result(
+        game_info,
+        GameResultInfo::new(game_result),
+    ))
+}
+
+/**
+ * Accepts the game result flagged for me with the given hash, regardless of the decision of the EloRatingSystem
+ */
+pub(crate) fn accept_flagged_game_result<S: EloRatingSystem>(
+    game_result_hash: EntryHash,
+) -> ExternResult<()> {
+    let unpublished_game_result = get_my_unpublished_game_results()?
+        .into_iter()
+        .find(|unpublished| unpublished.game_result_hash.eq(&game_result_hash));
+
+    if let Some(unpublished_game_result) = unpublished_game_result {
+        if have_i_published_game(&unpublished_game_result.game_result.game_id)? {
+            delete_link(unpublished_game_result.flag_link_hash)?;
+        } else {
+            create_game_result_and_resolve_flag::<S>(
+                unpublished_game_result.game_result,
+                unpublished_game_result.flag_link_hash,
+            )?;
+        }
     }
 
     Ok(())
@@ -27,55 +178,132 @@ pub fn try_resolve_unpublished_game_results<S: EloRatingSystem>() -> ExternResul
 #[derive(Serialize, Deserialize, Debug)]
 struct UnpublishedGameResult {
     game_result: GameResult,
+    game_result_hash: EntryHash,
     flag_link_hash: HeaderHash,
 }
 
 fn get_my_unpublished_game_results() -> ExternResult<Vec<UnpublishedGameResult>> {
+    let game_results = get_my_unpublished_game_flags()?
+        .into_iter()
+        .filter_map(|(link, maybe_game_result)| {
+            maybe_game_result.map(|game_result| UnpublishedGameResult {
+                game_result,
+                game_result_hash: link.target,
+                flag_link_hash: link.create_link_hash,
+            })
+        })
+        .collect();
+
+    Ok(game_results)
+}
+
+/**
+ * All the unpublished_game flags attached to me, with the game result they point to if it can still be fetched
+ */
+fn get_my_unpublished_game_flags() -> ExternResult<Vec<(Link, Option<GameResult>)>> {
     let my_pub_key = agent_info()?.agent_latest_pubkey;
 
     let unpublished_links = get_links(my_pub_key.clone().into(), Some(unpublished_game_tag()))?;
 
     let get_inputs = unpublished_links
-        .clone()
-        .into_iter()
-        .map(|link| GetInput::new(link.target.into(), GetOptions::default()))
+        .iter()
+        .map(|link| GetInput::new(link.target.clone().into(), GetOptions::default()))
         .collect();
 
     let maybe_elements = HDK.with(|hdk| hdk.borrow().get(get_inputs))?;
 
-    let create_link_hashes: Vec<HeaderHash> = unpublished_links
+    let flags = unpublished_links
         .into_iter()
-        .map(|l| l.create_link_hash)
+        .zip(maybe_elements.into_iter())
+        .map(|(link, maybe_element)| {
+            let maybe_game_result = maybe_element
+                .and_then(|element| element_to_game_result(element).ok())
+                .map(|(_, game_result)| game_result);
+
+            (link, maybe_game_result)
+        })
         .collect();
 
-    // TODO: Check whether I've already published this game
+    Ok(flags)
+}
 
-    let game_results = maybe_elements
-        .into_iter()
-        .zip(create_link_hashes.into_iter())
-        .filter_map(|(maybe_element, create_link_hash)| {
-            maybe_element.map(|e| (e, create_link_hash))
-        })
-        .filter_map(|(e, create_link_header)| {
-            let game_result: ExternResult<(HeaderHashed, GameResult)> = element_to_game_result(e);
-            match game_result {
-                Ok((_, gr)) => Some(UnpublishedGameResult {
-                    game_result: gr,
-                    flag_link_hash: create_link_header,
-                }),
-                _ => None,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum StaleFlagReason {
+    // The flag is older than the unpublished_game_flags_ttl_in_mins of the EloRatingSystem
+    Expired,
+    // The flagged game result still can't be fetched, or is not a game result, once the flag is older than the TTL
+    Unresolvable,
+    // I already have a game result for the flagged game
+    AlreadyPublished,
+    // The author of the flagged game result is in my block list
+    Blocked,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CleanedUnpublishedGameFlag {
+    pub flag_link_hash: HeaderHashB64,
+    pub game_result_hash: EntryHashB64,
+    pub reason: StaleFlagReason,
+}
+
+/**
+ * Deletes the unpublished_game flags attached to me that can't or shouldn't be resolved anymore,
+ * and returns which ones were deleted and why
+ */
+pub fn clean_stale_unpublished_game_flags<S: EloRatingSystem>(
+) -> ExternResult<Vec<CleanedUnpublishedGameFlag>> {
+    let now = sys_time()?.as_micros();
+    let ttl_in_micros = S::unpublished_game_flags_ttl_in_mins() as i64 * 60 * 1_000_000;
+
+    let mut cleaned_flags: Vec<CleanedUnpublishedGameFlag> = Vec::new();
+
+    for (link, maybe_game_result) in get_my_unpublished_game_flags()? {
+        let expired = now - link.timestamp.as_micros() > ttl_in_micros;
+
+        let maybe_reason = match maybe_game_result {
+            // The game result may just not have been gossiped to me yet, so the flag is kept until it expires
+            None if expired => Some(StaleFlagReason::Unresolvable),
+            None => None,
+            Some(game_result) => {
+                if is_blocked(&game_result.opponent()?)? {
+                    Some(StaleFlagReason::Blocked)
+                } else if have_i_published_game(&game_result.game_id)? {
+                    Some(StaleFlagReason::AlreadyPublished)
+                } else if expired {
+                    Some(StaleFlagReason::Expired)
+                } else {
+                    None
+                }
             }
-        })
-        .collect();
+        };
 
-    Ok(game_results)
+        if let Some(reason) = maybe_reason {
+            delete_link(link.create_link_hash.clone())?;
+
+            cleaned_flags.push(CleanedUnpublishedGameFlag {
+                flag_link_hash: link.create_link_hash.into(),
+                game_result_hash: link.target.into(),
+                reason,
+            });
+        }
+    }
+
+    Ok(cleaned_flags)
 }
 
 pub(crate) fn create_game_result_and_resolve_flag<S: EloRatingSystem>(
     game_result: GameResult,
     create_link_hash: HeaderHash,
 ) -> ExternResult<HeaderHash> {
-    rebase_game_result::<S>(&mut game_result.clone())?;
+    if have_i_published_game(&game_result.game_id)? {
+        return Err(WasmError::Guest(format!(
+            "I have already published a game result for the game {}",
+            game_result.game_id
+        )));
+    }
+
+    let game_result = rebase_game_result::<S>(&game_result)?;
 
     let header_hash = create_entry(game_result.clone())?;
 
@@ -83,13 +311,26 @@ pub(crate) fn create_game_result_and_resolve_flag<S: EloRatingSystem>(
 
     delete_link(create_link_hash)?;
 
-    index_game_result_if_not_exists::<S>(game_result, game_result_hash.clone())?;
+    index_game_result_if_not_exists::<S>(
+        AnyGameResult::OneVsOne(game_result),
+        game_result_hash.clone(),
+    )?;
 
     Ok(header_hash)
 }
 
-fn rebase_game_result<S: EloRatingSystem>(old_game_result: &mut GameResult) -> ExternResult<()> {
-    let maybe_my_last_game_result = get_my_last_game_result()?;
+/**
+ * Recomputes my EloUpdate in the flagged game result from my actual latest game result,
+ * since I may have played other games after the opponent created it
+ *
+ * The EloUpdate of the opponent is kept as they published it: their rating already moved with their own entry,
+ * and validation only binds the EloUpdate of the author in a game result that wasn't countersigned
+ */
+fn rebase_game_result<S: EloRatingSystem>(
+    old_game_result: &GameResult,
+) -> ExternResult<GameResult> {
+    let maybe_my_last_game_result =
+        get_my_last_game_result::<S>(&category_for::<S>(&old_game_result.game_info)?)?;
 
     // Get the previous game result for the opponent
     let opponent = old_game_result.opponent()?;
@@ -107,7 +348,7 @@ fn rebase_game_result<S: EloRatingSystem>(old_game_result: &mut GameResult) -> E
             )?;
 
             match element {
-                Some(e) => Ok(Some(element_to_game_result(e)?)),
+                Some(e) => Ok(Some(element_to_any_game_result(e)?)),
                 None => Err(WasmError::Guest(
                     "Cannot get the last game for counterparty".into(),
                 )),
@@ -117,42 +358,45 @@ fn rebase_game_result<S: EloRatingSystem>(old_game_result: &mut GameResult) -> E
     }?;
 
     let my_pub_key = AgentPubKeyB64::from(agent_info()?.agent_latest_pubkey);
-    let opponent_previous_elo =
-        elo_rating_from_last_game_result::<S>(&opponent, &previous_game_result)?;
-    let my_previous_elo =
-        elo_rating_from_last_game_result::<S>(&my_pub_key, &maybe_my_last_game_result)?;
+    let season = season_for::<S>(&old_game_result.game_info)?;
+    let opponent_history =
+        player_history_from_last_game_result::<S>(&opponent, &previous_game_result, &season)?;
+    let my_history = player_history_from_last_game_result::<S>(
+        &my_pub_key,
+        &maybe_my_last_game_result,
+        &season,
+    )?;
 
     let am_i_player_a = old_game_result.player_a.player_address.eq(&my_pub_key);
 
     let (player_a, player_b) = match am_i_player_a {
-        true => (my_previous_elo, opponent_previous_elo),
-        false => (opponent_previous_elo, my_previous_elo),
+        true => (&my_history, &opponent_history),
+        false => (&opponent_history, &my_history),
     };
 
-    let (player_a_new_elo, player_b_new_elo) = skill_rating::elo::game(
-        player_a,
-        player_b,
-        old_game_result.score_player_a,
-        S::k_factor(),
-        S::k_factor(),
-    );
+    let (player_a_new_rating, player_b_new_rating) =
+        rate_game::<S>(player_a, player_b, old_game_result.score_player_a);
 
     let previous_game_result =
         maybe_my_last_game_result.map(|(h, _)| HeaderHashB64::from(h.into_hash()));
 
+    let mut new_game_result = old_game_result.clone();
+
     if am_i_player_a {
-        old_game_result.player_a = EloUpdate {
-            player_address: my_pub_key,
-            current_elo: player_a_new_elo,
+        new_game_result.player_a = EloUpdate::new::<S::RatingSystem>(
+            my_pub_key,
+            &my_history,
+            &player_a_new_rating,
             previous_game_result,
-        };
+        )?;
     } else {
-        old_game_result.player_b = EloUpdate {
-            player_address: my_pub_key,
-            current_elo: player_b_new_elo,
+        new_game_result.player_b = EloUpdate::new::<S::RatingSystem>(
+            my_pub_key,
+            &my_history,
+            &player_b_new_rating,
             previous_game_result,
-        };
+        )?;
     }
 
-    Ok(())
+    Ok(new_game_result)
 }
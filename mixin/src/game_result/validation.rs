@@ -1,13 +1,25 @@
-/*use hdk::prelude::holo_hash::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+use hdk::prelude::holo_hash::*;
 use hdk::prelude::*;
 
-use crate::{
-    elo_rating_system::EloRatingSystem,
-    game_result::{handlers::element_to_game_result, GameResult, GameResultInfo},
-};
+use crate::category::{category_of, elements_in_category};
+use crate::elo_rating_system::EloRatingSystem;
+use crate::season::is_season_open_at;
 
-use super::{handlers::internal_build_new_game_result, EloUpdate};
+use super::{
+    dispute::dispute_hash_for,
+    game_id::game_id_for,
+    handlers::{element_to_any_game_result, internal_build_new_game_result},
+    multiplayer::internal_build_new_multiplayer_game_result,
+    team::internal_build_new_team_game_result,
+    AnyGameResult, EloUpdate, GameResult, GameResultDispute, GameResultInfo, MultiplayerGameResult,
+    MultiplayerGameResultInfo, TeamGameResult, TeamGameResultInfo,
+};
 
+/**
+ * Validates any of the game result entries, whether they were committed unilaterally or countersigned
+ */
 pub fn validate_entry_game_result<S: EloRatingSystem>(
     validate_data: ValidateData,
 ) -> ExternResult<ValidateCallbackResult> {
@@ -30,144 +42,500 @@ pub fn validate_entry_game_result<S: EloRatingSystem>(
         ]));
     }
 
-    let (_, game_result) = element_to_game_result(validate_data.element.clone())?;
+    let (_, game_result) = element_to_any_game_result(validate_data.element.clone())?;
 
     validate_game_result::<S>(validate_data, game_result)
 }
 
 fn validate_game_result<S: EloRatingSystem>(
     validate_data: ValidateData,
-    game_result: GameResult,
+    game_result: AnyGameResult,
 ) -> ExternResult<ValidateCallbackResult> {
-    if game_result.score_player_a < 0_f32 || game_result.score_player_a > 1_f32 {
+    let structure_result = match &game_result {
+        AnyGameResult::OneVsOne(game_result) => validate_one_vs_one_structure(game_result),
+        AnyGameResult::Multiplayer(game_result) => validate_multiplayer_structure(game_result),
+        AnyGameResult::Team(game_result) => validate_team_structure(game_result),
+    };
+    if !is_valid(&structure_result) {
+        return Ok(structure_result);
+    }
+
+    let author = AgentPubKeyB64::from(validate_data.element.header().author().clone());
+
+    let elo_update = match game_result.elo_update_for(&author) {
+        Some(update) => update,
+        None => {
+            return Ok(ValidateCallbackResult::Invalid(String::from(
+                "The author of the element was not playing the game",
+            )))
+        }
+    };
+
+    if !is_season_open_at::<S>(
+        &game_result.game_info(),
+        validate_data.element.header().timestamp(),
+    )? {
         return Ok(ValidateCallbackResult::Invalid(String::from(
-            "The score of a player must be between 0_f32 and 1_f32 (both 0 and 1 included)",
+            "The season of the game was not being played when the game result was published",
         )));
     }
 
+    let package = validate_data.validation_package.ok_or(WasmError::Guest(
+        "Validation package was not preset for game result entry".into(),
+    ))?;
+
+    let previous_game_result_result =
+        validate_previous_game_result_hash::<S>(&package, &game_result, &elo_update)?;
+    if !is_valid(&previous_game_result_result) {
+        return Ok(previous_game_result_result);
+    }
+
+    let games_played_result = validate_games_played(&package, &elo_update)?;
+    if !is_valid(&games_played_result) {
+        return Ok(games_played_result);
+    }
+
+    let game_id_result = validate_game_not_published_before::<S>(&package, &game_result)?;
+    if !is_valid(&game_id_result) {
+        return Ok(game_id_result);
+    }
+
+    let players_previous_result = validate_previous_game_results_of_players::<S>(&game_result)?;
+    if !is_valid(&players_previous_result) {
+        return Ok(players_previous_result);
+    }
+
+    // Only a countersigned entry was signed by every player, so only then are their EloUpdates binding:
+    // a game result published unilaterally may carry an EloUpdate of the opponent computed at another time
+    let checked_agents = match validate_data.element.entry().as_option() {
+        Some(Entry::CounterSign(_, _)) => game_result.agents(),
+        _ => vec![author],
+    };
+
+    let validate_elo_result = validate_elo_updates_are_correct::<S>(&game_result, checked_agents)?;
+    if !is_valid(&validate_elo_result) {
+        return Ok(validate_elo_result);
+    }
+
+    let game_info = S::GameInfo::try_from(game_result.game_info()).or(Err(WasmError::Guest(
+        String::from("Could not convert game info"),
+    )))?;
+
+    match &game_result {
+        AnyGameResult::OneVsOne(game_result) => {
+            S::validate_game_result(game_info, GameResultInfo::new(game_result))
+        }
+        AnyGameResult::Multiplayer(game_result) => S::validate_multiplayer_game_result(
+            game_info,
+            MultiplayerGameResultInfo::new(game_result),
+        ),
+        AnyGameResult::Team(game_result) => {
+            S::validate_team_game_result(game_info, TeamGameResultInfo::new(game_result))
+        }
+    }
+}
+
+fn validate_one_vs_one_structure(game_result: &GameResult) -> ValidateCallbackResult {
+    if !is_valid_score(game_result.score_player_a) {
+        return ValidateCallbackResult::Invalid(String::from(
+            "The score of a player must be between 0_f32 and 1_f32 (both 0 and 1 included)",
+        ));
+    }
+
     if game_result
         .player_a
         .player_address
         .eq(&game_result.player_b.player_address)
     {
-        return Ok(ValidateCallbackResult::Invalid(String::from(
+        return ValidateCallbackResult::Invalid(String::from(
             "Cannot publish a result where a player plays against themselves",
-        )));
+        ));
     }
 
-    let author = validate_data.element.header().author();
+    ValidateCallbackResult::Valid
+}
 
-    let package = validate_data.validation_package.ok_or(WasmError::Guest(
-        "Validation package was not preset for game result entry".into(),
-    ))?;
+fn validate_multiplayer_structure(game_result: &MultiplayerGameResult) -> ValidateCallbackResult {
+    if game_result.players.len() < 2 {
+        return ValidateCallbackResult::Invalid(String::from(
+            "A multiplayer game needs at least two players",
+        ));
+    }
 
-    let elo_update = game_result.elo_update_for(&AgentPubKeyB64::from(author.clone()));
-    if let Some(update) = elo_update {
-        validate_previous_game_result_hash(&package, &update)?;
-    } else {
-        return Ok(ValidateCallbackResult::Invalid(String::from(
-            "The author of the element was not playing the game",
-        )));
+    if game_result.placements.len() != game_result.players.len() {
+        return ValidateCallbackResult::Invalid(String::from(
+            "There must be exactly one placement for each player",
+        ));
     }
 
-    let game_result_info = GameResultInfo::new(&game_result);
+    if has_repeated_players(&game_result.agents()) {
+        return ValidateCallbackResult::Invalid(String::from(
+            "Cannot publish a result where a player plays against themselves",
+        ));
+    }
 
-    let game_info = S::GameInfo::try_from(game_result.game_info.clone()).or(Err(
-        WasmError::Guest(String::from("Could not convert game info")),
-    ))?;
+    ValidateCallbackResult::Valid
+}
 
-    let validate_elo_result = validate_elo_update_is_correct::<S>(game_result)?;
+fn validate_team_structure(game_result: &TeamGameResult) -> ValidateCallbackResult {
+    if !is_valid_score(game_result.score_team_a) {
+        return ValidateCallbackResult::Invalid(String::from(
+            "The score of a team must be between 0_f32 and 1_f32 (both 0 and 1 included)",
+        ));
+    }
 
-    match validate_elo_result {
-        ValidateCallbackResult::Valid => S::validate_game_result(game_info, game_result_info),
-        _ => Ok(validate_elo_result),
+    if game_result.team_a.is_empty() || game_result.team_b.is_empty() {
+        return ValidateCallbackResult::Invalid(String::from(
+            "Both teams need at least one player",
+        ));
     }
+
+    if has_repeated_players(&game_result.agents()) {
+        return ValidateCallbackResult::Invalid(String::from(
+            "Cannot publish a result where a player plays against themselves",
+        ));
+    }
+
+    ValidateCallbackResult::Valid
 }
 
-fn validate_previous_game_result_hash(
+/**
+ * The previous game result must be the latest one of the author in the same category,
+ * skipping only the ones that were disputed
+ */
+fn validate_previous_game_result_hash<S: EloRatingSystem>(
     validation_package: &ValidationPackage,
+    game_result: &AnyGameResult,
     elo_update: &EloUpdate,
 ) -> ExternResult<ValidateCallbackResult> {
-    match elo_update.previous_game_result.clone() {
-        None => {
-            if validation_package.0.len() != 0 {
-                return Ok(ValidateCallbackResult::Invalid("The player has committed other game results but their previous_game_result was None".into()));
-            }
-        }
+    // Each category has its own chain of game results
+    let previous_game_results = elements_in_category::<S>(
+        game_result_elements(validation_package)?
+            .into_iter()
+            .cloned()
+            .collect(),
+        &category_of::<S>(game_result)?,
+    )?;
+
+    // Disputed game results don't count for the ratings, so they can be skipped
+    let skipped_game_results = match elo_update.previous_game_result.clone() {
+        None => previous_game_results,
         Some(hash) => {
-            if let Some(element) = validation_package.0.last() {
-                if !element.header_address().eq(&HeaderHash::from(hash)) {
+            let header_hash = HeaderHash::from(hash);
+            match previous_game_results
+                .iter()
+                .position(|element| element.header_address().eq(&header_hash))
+            {
+                Some(index) => previous_game_results[(index + 1)..].to_vec(),
+                None => {
                     return Ok(ValidateCallbackResult::Invalid(
-                        "previous_game_result was not the latest game result for the agent".into(),
-                    ));
+                        "previous_game_result was not a game result of this category".into(),
+                    ))
                 }
-            } else {
-                return Ok(ValidateCallbackResult::Invalid(
-                    "previous_game_hash was Some but no elements were in the validation package"
-                        .into(),
-                ));
             }
         }
+    };
+
+    for element in skipped_game_results {
+        let entry_hash = element.header().entry_hash().ok_or(WasmError::Guest(
+            "This header doesn't contain any entry hash".into(),
+        ))?;
+
+        // Fails with unresolved dependencies if the skipped game result was not disputed
+        must_get_entry(dispute_hash_for(entry_hash)?)?;
     }
+
     Ok(ValidateCallbackResult::Valid)
 }
 
-fn validate_elo_update_is_correct<S: EloRatingSystem>(
-    game_result: GameResult,
+/**
+ * The games played by the author must be the length of their chain of previous game results,
+ * walked through their source chain in the validation package
+ *
+ * The games played by the other players come from their own previous game result,
+ * which was validated in the same way when they published it
+ */
+fn validate_games_played(
+    validation_package: &ValidationPackage,
+    elo_update: &EloUpdate,
 ) -> ExternResult<ValidateCallbackResult> {
-    // Get player a previous game result
-    let mut player_a: Option<(HeaderHashed, GameResult)> = None;
-    if let Some(previous_game_result_hash) = game_result.player_a.previous_game_result.clone() {
-        let element = must_get_valid_element(previous_game_result_hash.into())?;
+    let elements_by_header: BTreeMap<HeaderHash, &Element> =
+        game_result_elements(validation_package)?
+            .into_iter()
+            .map(|element| (element.header_address().clone(), element))
+            .collect();
 
-        if element.entry().clone().into_option().is_none() {
-            let entry_hash = element.header().entry_hash().ok_or(WasmError::Guest(
-                "This header doesn't contain any entry hash".into(),
-            ))?;
-            return Ok(ValidateCallbackResult::UnresolvedDependencies(vec![
-                entry_hash.clone().into(),
-            ]));
-        }
+    let mut games_played = 1;
+    let mut previous_game_result = elo_update.previous_game_result.clone();
 
-        let (_, game_result) = element_to_game_result(element.clone())?;
+    while let Some(previous_game_result_hash) = previous_game_result {
+        let element = match elements_by_header.get(&HeaderHash::from(previous_game_result_hash)) {
+            Some(element) => *element,
+            None => {
+                return Ok(ValidateCallbackResult::Invalid(
+                    "previous_game_result was not a game result of the author".into(),
+                ))
+            }
+        };
+        let (_, game_result) = element_to_any_game_result(element.clone())?;
 
-        player_a = Some((element.header_hashed().clone(), game_result));
+        previous_game_result = game_result
+            .elo_update_for(&elo_update.player_address)
+            .and_then(|previous_elo_update| previous_elo_update.previous_game_result);
+        games_played += 1;
     }
 
-    // Get player b previous game result
-    let mut player_b: Option<(HeaderHashed, GameResult)> = None;
-    if let Some(previous_game_result_hash) = game_result.player_b.previous_game_result.clone() {
-        let element = must_get_valid_element(previous_game_result_hash.into())?;
+    if games_played != elo_update.games_played {
+        return Ok(ValidateCallbackResult::Invalid(
+            "games_played doesn't match the chain of previous game results of the author".into(),
+        ));
+    }
 
-        if element.entry().clone().into_option().is_none() {
-            let entry_hash = element.header().entry_hash().ok_or(WasmError::Guest(
-                "This header doesn't contain any entry hash".into(),
-            ))?;
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/**
+ * A dispute can only be created by a player of a game result that was published without their countersignature,
+ * so its author can't dispute it
+ */
+pub fn validate_entry_game_result_dispute(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let dispute = match validate_data
+        .element
+        .entry()
+        .to_app_option::<GameResultDispute>()?
+    {
+        Some(dispute) => dispute,
+        None => {
+            let entry_hash =
+                validate_data
+                    .element
+                    .header()
+                    .entry_hash()
+                    .ok_or(WasmError::Guest(
+                        "This header doesn't contain any entry hash".into(),
+                    ))?;
             return Ok(ValidateCallbackResult::UnresolvedDependencies(vec![
                 entry_hash.clone().into(),
             ]));
         }
+    };
 
-        let (_, game_result) = element_to_game_result(element.clone())?;
+    let game_result_entry = must_get_entry(dispute.game_result_hash.clone().into())?;
 
-        player_b = Some((element.header_hashed().clone(), game_result));
+    let game_result = match GameResult::try_from(game_result_entry.as_content()) {
+        Ok(game_result) => game_result,
+        Err(_) => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "Only game results published without countersigning can be disputed".into(),
+            ))
+        }
+    };
+
+    let author = AgentPubKeyB64::from(validate_data.element.header().author().clone());
+
+    if game_result.elo_update_for(&author).is_none() {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the players of a game result can dispute it".into(),
+        ));
     }
 
-    // Compute new game result and see it's equal
-    let new_game_result = internal_build_new_game_result::<S>(
-        game_result.game_info.clone(),
-        &game_result.player_a.player_address,
-        &game_result.player_b.player_address,
-        game_result.score_player_a,
-        player_a,
-        player_b,
-    )?;
+    let package = validate_data.validation_package.ok_or(WasmError::Guest(
+        "Validation package was not preset for game result dispute entry".into(),
+    ))?;
+
+    let game_result_hash = EntryHash::from(dispute.game_result_hash);
+    let published_by_author = game_result_elements(&package)?
+        .into_iter()
+        .any(|element| element.header().entry_hash().eq(&Some(&game_result_hash)));
+
+    if published_by_author {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The author of a game result can't dispute it".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/**
+ * A player can only publish one game result for each game
+ */
+fn validate_game_not_published_before<S: EloRatingSystem>(
+    validation_package: &ValidationPackage,
+    game_result: &AnyGameResult,
+) -> ExternResult<ValidateCallbackResult> {
+    let game_id = game_result.game_id();
 
-    if !new_game_result.eq(&game_result) {
+    if !game_id.eq(&game_id_for::<S>(&game_result.game_info())?) {
         return Ok(ValidateCallbackResult::Invalid(String::from(
-            "Invalid ELO score update",
+            "The game id doesn't match the game info",
         )));
     }
 
+    for element in game_result_elements(validation_package)? {
+        let (_, previous_game_result) = element_to_any_game_result(element.clone())?;
+
+        if previous_game_result.game_id().eq(&game_id) {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "The author has already published a game result for the game {}",
+                game_id
+            )));
+        }
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+// The validation package contains the whole source chain of the author,
+// but only the game results are part of the chain of EloUpdates
+fn game_result_elements(validation_package: &ValidationPackage) -> ExternResult<Vec<&Element>> {
+    let entry_types = AnyGameResult::entry_types()?;
+
+    Ok(validation_package
+        .0
+        .iter()
+        .filter(|element| match element.header().entry_type() {
+            Some(entry_type) => entry_types.contains(entry_type),
+            None => false,
+        })
+        .collect())
+}
+
+/**
+ * The previous game result of every player must be one they published themselves in the same category,
+ * even if their EloUpdate is not binding because they didn't countersign the game result
+ *
+ * Only the one of the author can be checked to be their latest, since it's the only source chain in the validation package
+ */
+fn validate_previous_game_results_of_players<S: EloRatingSystem>(
+    game_result: &AnyGameResult,
+) -> ExternResult<ValidateCallbackResult> {
+    let category = category_of::<S>(game_result)?;
+
+    for agent in game_result.agents() {
+        let previous_game_result_hash = match game_result
+            .elo_update_for(&agent)
+            .and_then(|elo_update| elo_update.previous_game_result)
+        {
+            Some(previous_game_result_hash) => previous_game_result_hash,
+            None => continue,
+        };
+
+        let element = must_get_valid_element(previous_game_result_hash.into())?;
+
+        if !AgentPubKeyB64::from(element.header().author().clone()).eq(&agent) {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The previous game result of a player must have been published by them".into(),
+            ));
+        }
+
+        let previous_game_result = match element_to_any_game_result(element) {
+            Ok((_, previous_game_result)) => previous_game_result,
+            Err(_) => {
+                return Ok(ValidateCallbackResult::Invalid(
+                    "The previous game result of a player must be a game result".into(),
+                ))
+            }
+        };
+
+        if !category_of::<S>(&previous_game_result)?.eq(&category) {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The previous game result of a player must be of the same category".into(),
+            ));
+        }
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/**
+ * Recomputes the game result from the previous game results of all the players,
+ * and checks that the EloUpdate of each of the given agents is the one that they should have gotten
+ */
+fn validate_elo_updates_are_correct<S: EloRatingSystem>(
+    game_result: &AnyGameResult,
+    checked_agents: Vec<AgentPubKeyB64>,
+) -> ExternResult<ValidateCallbackResult> {
+    let recomputed_game_result = match game_result {
+        AnyGameResult::OneVsOne(game_result) => {
+            AnyGameResult::OneVsOne(internal_build_new_game_result::<S>(
+                game_result.game_info.clone(),
+                &game_result.player_a.player_address,
+                &game_result.player_b.player_address,
+                game_result.score_player_a,
+                get_previous_game_result(&game_result.player_a)?,
+                get_previous_game_result(&game_result.player_b)?,
+            )?)
+        }
+        AnyGameResult::Multiplayer(game_result) => {
+            AnyGameResult::Multiplayer(internal_build_new_multiplayer_game_result::<S>(
+                game_result.game_info.clone(),
+                &game_result.agents(),
+                game_result.placements.clone(),
+                get_previous_game_results(&game_result.players)?,
+            )?)
+        }
+        AnyGameResult::Team(game_result) => {
+            let (team_a, team_b) = game_result.teams();
+            AnyGameResult::Team(internal_build_new_team_game_result::<S>(
+                game_result.game_info.clone(),
+                &team_a,
+                &team_b,
+                game_result.score_team_a,
+                get_previous_game_results(&game_result.team_a)?,
+                get_previous_game_results(&game_result.team_b)?,
+            )?)
+        }
+    };
+
+    for agent in checked_agents {
+        if !recomputed_game_result
+            .elo_update_for(&agent)
+            .eq(&game_result.elo_update_for(&agent))
+        {
+            return Ok(ValidateCallbackResult::Invalid(String::from(
+                "Invalid ELO score update",
+            )));
+        }
+    }
+
     Ok(ValidateCallbackResult::Valid)
-}*/
+}
+
+fn get_previous_game_results(
+    elo_updates: &Vec<EloUpdate>,
+) -> ExternResult<Vec<Option<(HeaderHashed, AnyGameResult)>>> {
+    elo_updates.iter().map(get_previous_game_result).collect()
+}
+
+fn get_previous_game_result(
+    elo_update: &EloUpdate,
+) -> ExternResult<Option<(HeaderHashed, AnyGameResult)>> {
+    match elo_update.previous_game_result.clone() {
+        None => Ok(None),
+        Some(previous_game_result_hash) => {
+            let element = must_get_valid_element(previous_game_result_hash.into())?;
+
+            Ok(Some(element_to_any_game_result(element)?))
+        }
+    }
+}
+
+pub(crate) fn is_valid(result: &ValidateCallbackResult) -> bool {
+    match result {
+        ValidateCallbackResult::Valid => true,
+        _ => false,
+    }
+}
+
+fn is_valid_score(score: f32) -> bool {
+    score >= 0_f32 && score <= 1_f32
+}
+
+fn has_repeated_players(players: &Vec<AgentPubKeyB64>) -> bool {
+    let different_players: BTreeSet<&AgentPubKeyB64> = players.iter().collect();
+
+    different_players.len() != players.len()
+}
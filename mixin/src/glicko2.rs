@@ -0,0 +1,151 @@
+use std::f64::consts::PI;
+
+use hdk::prelude::*;
+
+// Conversion factor between the Glicko scale and the Glicko-2 scale
+const GLICKO2_SCALE: f64 = 173.7178;
+// Convergence tolerance used when computing the new volatility
+const CONVERGENCE_TOLERANCE: f64 = 0.000001;
+// Upper bound for the volatility iteration, so that degenerate inputs can't loop forever
+const MAX_ITERATIONS: u32 = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Glicko2Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+/**
+ * Computes the new ratings for both players after a game, treating the game as its own rating period
+ */
+pub fn game(
+    player_a: Glicko2Rating,
+    player_b: Glicko2Rating,
+    score_player_a: f32,
+    tau: f64,
+) -> (Glicko2Rating, Glicko2Rating) {
+    let score_player_a = score_player_a as f64;
+
+    (
+        rate(player_a, &[(player_b, score_player_a)], tau),
+        rate(player_b, &[(player_a, 1.0 - score_player_a)], tau),
+    )
+}
+
+/**
+ * Computes the new rating of a player after playing the given games, each one given as (opponent, score)
+ */
+pub fn rate(player: Glicko2Rating, games: &[(Glicko2Rating, f64)], tau: f64) -> Glicko2Rating {
+    let mu = (player.rating - 1500.0) / GLICKO2_SCALE;
+    let phi = player.deviation / GLICKO2_SCALE;
+    let sigma = player.volatility;
+
+    if games.is_empty() {
+        let phi_star = (phi.powi(2) + sigma.powi(2)).sqrt();
+        return Glicko2Rating {
+            rating: player.rating,
+            deviation: phi_star * GLICKO2_SCALE,
+            volatility: sigma,
+        };
+    }
+
+    let mut inverse_v = 0.0;
+    let mut score_sum = 0.0;
+
+    for (opponent, score) in games {
+        let mu_j = (opponent.rating - 1500.0) / GLICKO2_SCALE;
+        let phi_j = opponent.deviation / GLICKO2_SCALE;
+
+        let g = g(phi_j);
+        let e = expected_score(mu, mu_j, g);
+
+        inverse_v += g.powi(2) * e * (1.0 - e);
+        score_sum += g * (score - e);
+    }
+
+    let v = 1.0 / inverse_v;
+    let delta = v * score_sum;
+
+    let new_sigma = new_volatility(phi, sigma, v, delta, tau);
+
+    let phi_star = (phi.powi(2) + new_sigma.powi(2)).sqrt();
+    let new_phi = 1.0 / (1.0 / phi_star.powi(2) + 1.0 / v).sqrt();
+    let new_mu = mu + new_phi.powi(2) * score_sum;
+
+    Glicko2Rating {
+        rating: new_mu * GLICKO2_SCALE + 1500.0,
+        deviation: new_phi * GLICKO2_SCALE,
+        volatility: new_sigma,
+    }
+}
+
+/**
+ * Aggregates a team into a single player: the average rating, and the quadratic mean of the deviations
+ */
+pub fn composite(team: &[Glicko2Rating]) -> Glicko2Rating {
+    let members = team.len().max(1) as f64;
+
+    Glicko2Rating {
+        rating: team.iter().map(|member| member.rating).sum::<f64>() / members,
+        deviation: (team
+            .iter()
+            .map(|member| member.deviation.powi(2))
+            .sum::<f64>()
+            / members)
+            .sqrt(),
+        volatility: team.iter().map(|member| member.volatility).sum::<f64>() / members,
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi.powi(2) / PI.powi(2)).sqrt()
+}
+
+fn expected_score(mu: f64, mu_j: f64, g: f64) -> f64 {
+    1.0 / (1.0 + (-g * (mu - mu_j)).exp())
+}
+
+// Illinois algorithm, as described in step 5 of Glickman's paper
+fn new_volatility(phi: f64, sigma: f64, v: f64, delta: f64, tau: f64) -> f64 {
+    let a = sigma.powi(2).ln();
+
+    let f = |x: f64| {
+        let e_x = x.exp();
+        e_x * (delta.powi(2) - phi.powi(2) - v - e_x) / (2.0 * (phi.powi(2) + v + e_x).powi(2))
+            - (x - a) / tau.powi(2)
+    };
+
+    let mut big_a = a;
+    let mut big_b = if delta.powi(2) > phi.powi(2) + v {
+        (delta.powi(2) - phi.powi(2) - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * tau) < 0.0 && k < MAX_ITERATIONS as f64 {
+            k += 1.0;
+        }
+        a - k * tau
+    };
+
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+
+    let mut iterations = 0;
+    while (big_b - big_a).abs() > CONVERGENCE_TOLERANCE && iterations < MAX_ITERATIONS {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+
+        if f_c * f_b <= 0.0 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a = f_a / 2.0;
+        }
+
+        big_b = big_c;
+        f_b = f_c;
+        iterations += 1;
+    }
+
+    (big_a / 2.0).exp()
+}
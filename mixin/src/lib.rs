@@ -1,26 +1,81 @@
+mod block_list;
+mod category;
 mod countersigning;
+mod decay;
 mod elo_ranking;
 mod elo_rating;
 mod elo_rating_system;
 mod game_result;
+mod glicko2;
 mod mixin;
+mod rating_system;
+mod remote_signal;
+mod season;
 
+pub use crate::block_list::{
+    block_agent, get_blocked_agents, get_marked_game_results_for_agents, unblock_agent,
+    BlockAgentInput, BlockedAgent, MarkedGameResult,
+};
 pub use crate::countersigning::{
-    receiver::handle_request_publish_game_result, sender::send_publish_game_result_request,
+    common::{
+        CountersigningError, GameResultRejection, ProposeGameResultResponse,
+        PublishGameResultResponse,
+    },
+    grants::{
+        grant_countersigning_capability, revoke_stale_countersigning_capabilities,
+        GrantCountersigningInput,
+    },
+    multiplayer::{
+        handle_commit_multiplayer_game_result, handle_request_publish_multiplayer_game_result,
+        send_publish_multiplayer_game_result_request,
+    },
+    proposals::{
+        accept_game_result_proposal, get_pending_game_result_proposals,
+        handle_commit_accepted_game_result, handle_propose_game_result,
+        reject_game_result_proposal, GameResultProposal, PendingGameResultProposal,
+        SentGameResultProposal,
+    },
+    queue::{
+        get_publication_queue, process_publication_queue, QueueEvent, QueuedGameResult,
+        QueuedGameResultEvent, QueuedGameResultState, QueuedGameResultStatus,
+    },
+    receiver::{handle_check_game_result, handle_request_publish_game_result},
+    sender::{send_publish_game_result_request, PublishGameResultOutcome, PublishPath},
+};
+pub use crate::decay::{
+    apply_inactivity_decay, inactivity_decay_tag, validate_entry_inactivity_decay, InactivityDecay,
+};
+pub use crate::elo_rating::{
+    fide_k_factor, get_elo_rating_for_agents, get_elo_rating_for_agents_in_category, AgentRating,
+    EloRating, GetEloRatingForAgentsInCategoryInput, PlayerHistory, RatingOf, DRAW, LOSS,
 };
-pub use crate::elo_rating::{get_elo_rating_for_agents, EloRating, DRAW, LOSS};
 pub use crate::elo_rating_system::*;
 pub use crate::game_result::{
+    dispute::{
+        dispute_acknowledged_tag, sync_my_ranking_with_disputes, withdraw_game_result_dispute,
+    },
+    game_id::{game_id_tag, get_game_results_for_game},
     handlers::{
-        element_to_game_result, game_results_tag, get_game_results_for_agents,
-        index_game_result_if_not_exists,
+        element_to_any_game_result, element_to_game_result, game_results_tag, game_results_tag_for,
+        get_game_results_for_agents, index_game_result_if_not_exists,
+    },
+    link_validation::{validate_create_link_game_result, validate_delete_link_game_result},
+    unpublished::{
+        clean_stale_unpublished_game_flags, try_resolve_unpublished_game_results,
+        CleanedUnpublishedGameFlag, StaleFlagReason,
     },
-    unpublished::try_resolve_unpublished_game_results,
-    //validation::validate_entry_game_result,
-    GameResult, GameResultInfo,
+    validation::{validate_entry_game_result, validate_entry_game_result_dispute},
+    AnyGameResult, EloSignal, EloUpdate, GameResult, GameResultDispute, GameResultInfo,
+    MultiplayerGameResult, MultiplayerGameResultInfo, TeamGameResult, TeamGameResultInfo,
+    UnpublishedGameRetry,
 };
+pub use crate::glicko2::Glicko2Rating;
+pub use crate::rating_system::{Elo, Glicko2, RatingSystem};
+pub use crate::remote_signal::{handle_elo_remote_signal, EloRemoteSignal};
+pub use crate::season::{get_season_standings, GetSeasonStandingsInput};
 pub use elo_ranking::*;
 pub use mixin::{
-    attempt_create_countersigned_game_result, create_game_result_and_flag, init_elo,
-    post_commit_elo,
+    attempt_create_countersigned_game_result, attempt_create_countersigned_multiplayer_game_result,
+    attempt_create_countersigned_team_game_result, create_game_result_and_flag, init_elo,
+    post_commit_elo, publish_game_result, queue_game_result,
 };
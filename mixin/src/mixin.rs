@@ -2,16 +2,35 @@ use hdk::prelude::holo_hash::*;
 use hdk::prelude::*;
 
 use crate::{
-    countersigning::sender::try_create_countersigned_game_result,
+    countersigning::{
+        grants::countersigning_functions,
+        multiplayer::{
+            try_create_countersigned_multiplayer_game_result,
+            try_create_countersigned_team_game_result,
+        },
+        queue::enqueue_game_result,
+        sender::{
+            publish_game_result_with_fallback, try_create_countersigned_game_result,
+            PublishGameResultOutcome,
+        },
+    },
+    elo_rating::is_provisional,
     game_result::handlers::{build_new_game_result, create_unilateral_game_result_and_flag},
-    put_elo_rating_in_ranking, EloRatingSystem, GameResult,
+    put_elo_rating_in_ranking, AnyGameResult, EloRatingSystem, RatingSystem,
 };
 
 pub fn init_elo<S: EloRatingSystem>() -> ExternResult<()> {
-    // TODO: restrict to only the agents with which we are playing
-    // grant unrestricted access to accept_cap_claim so other agents can send us claims
-    let mut functions: GrantedFunctions = BTreeSet::new();
-    functions.insert((zome_info()?.name, "request_publish_game_result".into()));
+    // If the EloRatingSystem requires it, only the opponents granted with grant_countersigning_capability
+    // can send us countersigning requests
+    let mut functions: GrantedFunctions = match S::countersigning_requires_grant() {
+        true => BTreeSet::new(),
+        false => countersigning_functions()?,
+    };
+    // grant unrestricted access to recv_remote_signal so other agents can send us their grants,
+    // each EloRemoteSignal is checked against its sender when it's received
+    functions.insert((zome_info()?.name, "recv_remote_signal".into()));
+    // only the game results I proposed are committed when the opponent accepts them, so no grant is needed
+    functions.insert((zome_info()?.name, "commit_accepted_game_result".into()));
     create_cap_grant(CapGrantEntry {
         tag: "".into(),
         // empty access converts to unrestricted
@@ -24,8 +43,11 @@ pub fn init_elo<S: EloRatingSystem>() -> ExternResult<()> {
     put_elo_rating_in_ranking::<S>(
         my_pub_key.clone().into(),
         my_pub_key,
+        &None,
+        &None,
         None,
-        S::initial_rating(),
+        S::RatingSystem::ranking_key(&S::initial_rating()),
+        is_provisional::<S>(0),
     )?;
 
     schedule("scheduled_try_resolve_unpublished_game_results")?;
@@ -34,10 +56,13 @@ pub fn init_elo<S: EloRatingSystem>() -> ExternResult<()> {
 }
 
 pub fn post_commit_elo(headers: Vec<SignedHeaderHashed>) -> ExternResult<()> {
-    let filter = ChainQueryFilter::new()
-        .entry_type(GameResult::entry_type()?)
-        .include_entries(true);
-    let elements = query(filter)?;
+    let mut elements: Vec<Element> = Vec::new();
+    for entry_type in AnyGameResult::entry_types()? {
+        let filter = ChainQueryFilter::new()
+            .entry_type(entry_type)
+            .include_entries(true);
+        elements.append(&mut query(filter)?);
+    }
 
     let header_hashes: Vec<HeaderHash> = headers
         .into_iter()
@@ -72,6 +97,8 @@ pub fn post_commit_elo(headers: Vec<SignedHeaderHashed>) -> ExternResult<()> {
 
 /**
  * Build a new GameResult for the finished game, and call_remote to the opponent with a countersigning request
+ *
+ * If the opponent approves game results manually, the returned hash is committed only once they accept it
  */
 pub fn attempt_create_countersigned_game_result<S: EloRatingSystem>(
     game_info: S::GameInfo,
@@ -85,6 +112,39 @@ pub fn attempt_create_countersigned_game_result<S: EloRatingSystem>(
     try_create_countersigned_game_result::<S>(bytes, opponent_address, my_score)
 }
 
+/**
+ * Build a new MultiplayerGameResult for the finished game, and countersign it with all the other players
+ *
+ * Placements go in the same order as the players, 1 being the winner and equal placements meaning a tie
+ */
+pub fn attempt_create_countersigned_multiplayer_game_result<S: EloRatingSystem>(
+    game_info: S::GameInfo,
+    players: Vec<AgentPubKeyB64>,
+    placements: Vec<u32>,
+) -> ExternResult<EntryHashB64> {
+    let bytes: SerializedBytes = game_info.try_into().or(Err(WasmError::Guest(String::from(
+        "Error converting game info into SerializedBytes",
+    ))))?;
+
+    try_create_countersigned_multiplayer_game_result::<S>(bytes, players, placements)
+}
+
+/**
+ * Build a new TeamGameResult for the finished game, and countersign it with all the other players of both teams
+ */
+pub fn attempt_create_countersigned_team_game_result<S: EloRatingSystem>(
+    game_info: S::GameInfo,
+    team_a: Vec<AgentPubKeyB64>,
+    team_b: Vec<AgentPubKeyB64>,
+    score_team_a: f32,
+) -> ExternResult<EntryHashB64> {
+    let bytes: SerializedBytes = game_info.try_into().or(Err(WasmError::Guest(String::from(
+        "Error converting game info into SerializedBytes",
+    ))))?;
+
+    try_create_countersigned_team_game_result::<S>(bytes, team_a, team_b, score_team_a)
+}
+
 /**
  * Build a new GameResult for the finished game, and call_remote to the opponent with a countersigning request
  */
@@ -101,6 +161,42 @@ pub fn create_game_result_and_flag<S: EloRatingSystem>(
     create_unilateral_game_result_and_flag::<S>(new_game_result)
 }
 
+/**
+ * Build a new GameResult for the finished game and try to countersign it with the opponent,
+ * falling back to publishing it unilaterally and flagging it if the opponent can't be reached
+ *
+ * If a session fails my source chain stays locked until the end of its window, and this returns without waiting for it:
+ * the outcome is Locked then, and publishing can be retried after it
+ */
+pub fn publish_game_result<S: EloRatingSystem>(
+    game_info: S::GameInfo,
+    opponent_address: AgentPubKeyB64,
+    my_score: f32,
+) -> ExternResult<PublishGameResultOutcome> {
+    let bytes: SerializedBytes = game_info.try_into().or(Err(WasmError::Guest(String::from(
+        "Error converting game info into SerializedBytes",
+    ))))?;
+
+    let new_game_result = build_new_game_result::<S>(bytes, &opponent_address, my_score)?;
+    publish_game_result_with_fallback::<S>(new_game_result)
+}
+
+/**
+ * Add the game result for the finished game to my publication queue, where it's published after the ones
+ * queued before it, returning the hash that identifies it in the queue
+ */
+pub fn queue_game_result<S: EloRatingSystem>(
+    game_info: S::GameInfo,
+    opponent_address: AgentPubKeyB64,
+    my_score: f32,
+) -> ExternResult<HeaderHashB64> {
+    let bytes: SerializedBytes = game_info.try_into().or(Err(WasmError::Guest(String::from(
+        "Error converting game info into SerializedBytes",
+    ))))?;
+
+    enqueue_game_result::<S>(bytes, opponent_address, my_score)
+}
+
 #[macro_export]
 macro_rules! mixin_elo {
     ( $elo_rating_system:ty ) => {
@@ -112,8 +208,47 @@ macro_rules! mixin_elo {
          * Get the next chunk for the ELO ranking
          */
         #[hdk_extern]
-        pub fn get_elo_ranking_chunk(input: GetEloRankingChunkInput) -> ExternResult<EloRanking> {
-            $crate::get_elo_ranking_chunk(input.from_elo, input.agent_count)
+        pub fn get_elo_ranking_chunk(
+            input: GetEloRankingChunkInput,
+        ) -> ExternResult<$crate::EloRankingChunk> {
+            $crate::get_elo_ranking_chunk::<$elo_rating_system>(input)
+        }
+
+        /**
+         * Get the final ranking of a season that has already ended
+         */
+        #[hdk_extern]
+        pub fn get_season_standings(
+            input: $crate::GetSeasonStandingsInput,
+        ) -> ExternResult<EloRanking> {
+            $crate::get_season_standings::<$elo_rating_system>(input.season, input.category)
+        }
+
+        /**
+         * Get the position of the given agent in the ranking
+         */
+        #[hdk_extern]
+        pub fn get_agent_rank_position(
+            input: $crate::GetAgentRankPositionInput,
+        ) -> ExternResult<$crate::AgentRankPosition> {
+            $crate::get_agent_rank_position::<$elo_rating_system>(
+                input.agent_pub_key,
+                input.category,
+            )
+        }
+
+        /**
+         * Get the agents ranked just above and below the given agent
+         */
+        #[hdk_extern]
+        pub fn get_ranking_neighbourhood(
+            input: $crate::GetRankingNeighbourhoodInput,
+        ) -> ExternResult<EloRanking> {
+            $crate::get_ranking_neighbourhood::<$elo_rating_system>(
+                input.agent_pub_key,
+                input.agent_count,
+                input.category,
+            )
         }
 
         /**
@@ -122,32 +257,113 @@ macro_rules! mixin_elo {
         #[hdk_extern]
         pub fn get_elo_rating_for_agents(
             agent_pub_keys: Vec<AgentPubKeyB64>,
-        ) -> ExternResult<BTreeMap<AgentPubKeyB64, $crate::EloRating>> {
+        ) -> ExternResult<
+            BTreeMap<AgentPubKeyB64, $crate::AgentRating<$crate::RatingOf<$elo_rating_system>>>,
+        > {
             $crate::get_elo_rating_for_agents::<$elo_rating_system>(agent_pub_keys)
         }
 
+        /**
+         * Get the ELO ratings for the given users in the given category
+         */
+        #[hdk_extern]
+        pub fn get_elo_rating_for_agents_in_category(
+            input: $crate::GetEloRatingForAgentsInCategoryInput,
+        ) -> ExternResult<
+            BTreeMap<AgentPubKeyB64, $crate::AgentRating<$crate::RatingOf<$elo_rating_system>>>,
+        > {
+            $crate::get_elo_rating_for_agents_in_category::<$elo_rating_system>(
+                input.agent_pub_keys,
+                &input.category,
+            )
+        }
+
+        /**
+         * Checks a GameResult that the opponent is about to countersign with me, before they start the session
+         */
+        #[hdk_extern]
+        pub fn check_game_result(
+            game_result: $crate::GameResult,
+        ) -> ExternResult<Option<$crate::GameResultRejection>> {
+            $crate::handle_check_game_result::<$elo_rating_system>(game_result)
+        }
+
         /**
          * Receives a request to publish a countersigned GameResult
          */
         #[hdk_extern]
         pub fn request_publish_game_result(
             counterparty_preflight_response: PreflightResponse,
-        ) -> ExternResult<PreflightResponse> {
+        ) -> ExternResult<$crate::PublishGameResultResponse> {
             $crate::handle_request_publish_game_result::<$elo_rating_system>(
                 counterparty_preflight_response,
             )
         }
 
+        /**
+         * Receives a request to publish a countersigned MultiplayerGameResult or TeamGameResult
+         */
+        #[hdk_extern]
+        pub fn request_publish_multiplayer_game_result(
+            initiator_preflight_response: PreflightResponse,
+        ) -> ExternResult<$crate::PublishGameResultResponse> {
+            $crate::handle_request_publish_multiplayer_game_result::<$elo_rating_system>(
+                initiator_preflight_response,
+            )
+        }
+
+        /**
+         * Receives the preflight responses of all the players and commits the countersigned game result
+         */
+        #[hdk_extern]
+        pub fn commit_multiplayer_game_result(
+            responses: Vec<PreflightResponse>,
+        ) -> ExternResult<EntryHashB64> {
+            $crate::handle_commit_multiplayer_game_result(responses)
+        }
+
+        /**
+         * Receives a GameResult proposed by the opponent, stored until my user accepts or rejects it
+         */
+        #[hdk_extern]
+        pub fn propose_game_result(
+            game_result: $crate::GameResult,
+        ) -> ExternResult<$crate::ProposeGameResultResponse> {
+            $crate::handle_propose_game_result::<$elo_rating_system>(game_result)
+        }
+
+        /**
+         * Receives the acceptance of a game result I proposed, and commits my part of the countersigned entry
+         */
+        #[hdk_extern]
+        pub fn commit_accepted_game_result(
+            acceptor_preflight_response: PreflightResponse,
+        ) -> ExternResult<$crate::PublishGameResultResponse> {
+            $crate::handle_commit_accepted_game_result::<$elo_rating_system>(
+                acceptor_preflight_response,
+            )
+        }
+
         /**
          * Get the game results for the given agents
          */
         #[hdk_extern]
         pub fn get_game_results_for_agents(
             agent_pub_keys: Vec<AgentPubKeyB64>,
-        ) -> ExternResult<BTreeMap<AgentPubKeyB64, Vec<(HeaderHashed, $crate::GameResult)>>> {
+        ) -> ExternResult<BTreeMap<AgentPubKeyB64, Vec<(HeaderHashed, $crate::AnyGameResult)>>> {
             $crate::get_game_results_for_agents(agent_pub_keys)
         }
 
+        /**
+         * Get all the game results published for the given game
+         */
+        #[hdk_extern]
+        pub fn get_game_results_for_game(
+            game_id: String,
+        ) -> ExternResult<Vec<(EntryHashB64, $crate::AnyGameResult)>> {
+            $crate::get_game_results_for_game(game_id)
+        }
+
         /**
          * Called from post_commit, index the game result
          */
@@ -159,7 +375,7 @@ macro_rules! mixin_elo {
                 let element = get(hash.clone(), GetOptions::default())?
                     .ok_or(WasmError::Guest("Could not get game result".into()))?;
 
-                let (_, game_result) = $crate::element_to_game_result(element)?;
+                let (_, game_result) = $crate::element_to_any_game_result(element)?;
 
                 $crate::index_game_result_if_not_exists::<$elo_rating_system>(game_result.clone(), hash.clone())?;
             }
@@ -168,25 +384,218 @@ macro_rules! mixin_elo {
         }
 
         /**
-         * Get the game results for the given agents
+         * Allow the given opponents to send me countersigning requests, for one game or until they are used
+         */
+        #[hdk_extern]
+        pub fn grant_countersigning_capability(
+            input: $crate::GrantCountersigningInput,
+        ) -> ExternResult<()> {
+            $crate::grant_countersigning_capability(input)
+        }
+
+        /**
+         * Add the given agent to my block list, refusing their countersigning requests and flags
+         */
+        #[hdk_extern]
+        pub fn block_agent(input: $crate::BlockAgentInput) -> ExternResult<()> {
+            $crate::block_agent(input)
+        }
+
+        /**
+         * Remove the given agent from my block list
+         */
+        #[hdk_extern]
+        pub fn unblock_agent(agent: AgentPubKeyB64) -> ExternResult<()> {
+            $crate::unblock_agent(agent)
+        }
+
+        /**
+         * Get the agents in my block list
+         */
+        #[hdk_extern]
+        pub fn get_blocked_agents(_: ()) -> ExternResult<Vec<$crate::BlockedAgent>> {
+            $crate::get_blocked_agents()
+        }
+
+        /**
+         * Get the game results for the given agents, marking the ones played with agents I have blocked
+         */
+        #[hdk_extern]
+        pub fn get_marked_game_results_for_agents(
+            agent_pub_keys: Vec<AgentPubKeyB64>,
+        ) -> ExternResult<BTreeMap<AgentPubKeyB64, Vec<$crate::MarkedGameResult>>> {
+            $crate::get_marked_game_results_for_agents(agent_pub_keys)
+        }
+
+        /**
+         * Get the game results in my publication queue, with their status
+         */
+        #[hdk_extern]
+        pub fn get_publication_queue(_: ()) -> ExternResult<Vec<$crate::QueuedGameResultState>> {
+            $crate::get_publication_queue::<$elo_rating_system>()
+        }
+
+        /**
+         * Get the countersigning proposals waiting for my approval
+         */
+        #[hdk_extern]
+        pub fn get_pending_game_result_proposals(
+            _: (),
+        ) -> ExternResult<Vec<$crate::PendingGameResultProposal>> {
+            $crate::get_pending_game_result_proposals()
+        }
+
+        /**
+         * Accept the proposal for the given game result, countersigning it with the opponent
+         */
+        #[hdk_extern]
+        pub fn accept_game_result_proposal(
+            game_result_hash: EntryHashB64,
+        ) -> ExternResult<EntryHashB64> {
+            $crate::accept_game_result_proposal::<$elo_rating_system>(game_result_hash)
+        }
+
+        /**
+         * Reject the proposal for the given game result
+         */
+        #[hdk_extern]
+        pub fn reject_game_result_proposal(game_result_hash: EntryHashB64) -> ExternResult<()> {
+            $crate::reject_game_result_proposal(game_result_hash)
+        }
+
+        /**
+         * Delete the expired and unresolvable unpublished_game flags attached to me, reporting why
+         */
+        #[hdk_extern]
+        pub fn clean_stale_unpublished_game_flags(
+            _: (),
+        ) -> ExternResult<Vec<$crate::CleanedUnpublishedGameFlag>> {
+            $crate::clean_stale_unpublished_game_flags::<$elo_rating_system>()
+        }
+
+        /**
+         * Withdraw my dispute of the given game result, accepting it
+         */
+        #[hdk_extern]
+        pub fn withdraw_game_result_dispute(game_result_hash: EntryHashB64) -> ExternResult<()> {
+            $crate::withdraw_game_result_dispute::<$elo_rating_system>(game_result_hash)
+        }
+
+        /**
+         * Receive the signals sent by other agents, like the dispute of one of my game results
+         */
+        #[hdk_extern]
+        pub fn recv_remote_signal(signal: ExternIO) -> ExternResult<()> {
+            let elo_remote_signal: $crate::EloRemoteSignal = signal.decode()?;
+            $crate::handle_elo_remote_signal::<$elo_rating_system>(elo_remote_signal)
+        }
+
+        /**
+         * Runs every minute: each of its tasks decides whether it's time to do its work,
+         * like the unpublished game results whose next retry is due
+         *
+         * Every task runs even if the previous ones failed, and each failure is emitted as a signal
          */
         #[hdk_extern(infallible)]
         pub fn scheduled_try_resolve_unpublished_game_results(
             _: Option<Schedule>,
         ) -> Option<Schedule> {
-            let _r = $crate::try_resolve_unpublished_game_results::<$elo_rating_system>();
+            if let Err(error) = $crate::try_resolve_unpublished_game_results::<$elo_rating_system>()
+            {
+                let _r = emit_signal($crate::EloSignal::ResolveUnpublishedGameResultsFailed {
+                    error: format!("{:?}", error),
+                });
+            }
+            if let Err(error) = $crate::process_publication_queue::<$elo_rating_system>() {
+                let _r = emit_signal($crate::EloSignal::ProcessPublicationQueueFailed {
+                    error: format!("{:?}", error),
+                });
+            }
+            if let Err(error) =
+                $crate::revoke_stale_countersigning_capabilities::<$elo_rating_system>()
+            {
+                let _r = emit_signal(
+                    $crate::EloSignal::RevokeStaleCountersigningCapabilitiesFailed {
+                        error: format!("{:?}", error),
+                    },
+                );
+            }
+            if let Err(error) = $crate::apply_inactivity_decay::<$elo_rating_system>() {
+                let _r = emit_signal($crate::EloSignal::ApplyInactivityDecayFailed {
+                    error: format!("{:?}", error),
+                });
+            }
             Some(Schedule::Persisted(format!("* * * * *")))
         }
 
-        /*
         /**
-         * Validate the game_results entry
-        */  
+         * Validate the game_result entry
+         */
         #[hdk_extern]
         pub fn validate_create_entry_game_result(
             validate_data: ValidateData,
         ) -> ExternResult<ValidateCallbackResult> {
             $crate::validate_entry_game_result::<$elo_rating_system>(validate_data)
-        }*/
+        }
+
+        /**
+         * Validate the multiplayer_game_result entry
+         */
+        #[hdk_extern]
+        pub fn validate_create_entry_multiplayer_game_result(
+            validate_data: ValidateData,
+        ) -> ExternResult<ValidateCallbackResult> {
+            $crate::validate_entry_game_result::<$elo_rating_system>(validate_data)
+        }
+
+        /**
+         * Validate the team_game_result entry
+         */
+        #[hdk_extern]
+        pub fn validate_create_entry_team_game_result(
+            validate_data: ValidateData,
+        ) -> ExternResult<ValidateCallbackResult> {
+            $crate::validate_entry_game_result::<$elo_rating_system>(validate_data)
+        }
+
+        /**
+         * Validate the game_result_dispute entry
+         */
+        #[hdk_extern]
+        pub fn validate_create_entry_game_result_dispute(
+            validate_data: ValidateData,
+        ) -> ExternResult<ValidateCallbackResult> {
+            $crate::validate_entry_game_result_dispute(validate_data)
+        }
+
+        /**
+         * Validate the inactivity_decay entry
+         */
+        #[hdk_extern]
+        pub fn validate_create_entry_inactivity_decay(
+            validate_data: ValidateData,
+        ) -> ExternResult<ValidateCallbackResult> {
+            $crate::validate_entry_inactivity_decay::<$elo_rating_system>(validate_data)
+        }
+
+        /**
+         * Validate the game_result and unpublished_game links
+         */
+        #[hdk_extern]
+        pub fn validate_create_link(
+            validate_data: ValidateCreateLinkData,
+        ) -> ExternResult<ValidateCallbackResult> {
+            $crate::validate_create_link_game_result::<$elo_rating_system>(validate_data)
+        }
+
+        /**
+         * Validate the deletion of game_result and unpublished_game links
+         */
+        #[hdk_extern]
+        pub fn validate_delete_link(
+            validate_data: ValidateDeleteLinkData,
+        ) -> ExternResult<ValidateCallbackResult> {
+            $crate::validate_delete_link_game_result(validate_data)
+        }
     };
 }
@@ -0,0 +1,253 @@
+use std::fmt::Debug;
+
+use hdk::prelude::*;
+use serde::de::DeserializeOwned;
+
+use crate::elo_rating::EloRating;
+use crate::glicko2::{self, Glicko2Rating};
+
+/**
+ * The math behind the ratings: how the rating of a player is represented and how it changes after a game
+ */
+pub trait RatingSystem {
+    // Rating of a single player, stored serialized in each EloUpdate
+    type Rating: Clone + Debug + PartialEq + Serialize + DeserializeOwned;
+
+    // Rating for a player who hasn't played any games, unless the EloRatingSystem overrides it
+    fn default_initial_rating() -> Self::Rating;
+
+    // Projection of the rating that is used as the key in the ELO_RANKING_INDEX
+    fn ranking_key(rating: &Self::Rating) -> i64;
+
+    // New ratings for both players after a game where player A got score_player_a
+    fn rate_game(
+        player_a: &Self::Rating,
+        player_b: &Self::Rating,
+        score_player_a: f32,
+        k_factor_player_a: u32,
+        k_factor_player_b: u32,
+    ) -> (Self::Rating, Self::Rating);
+
+    // New rating of a player after a game against several opponents at once,
+    // each of them given with the score that the player got against them
+    fn rate_multiplayer_game(
+        player: &Self::Rating,
+        opponents: &[(Self::Rating, f32)],
+        k_factor: u32,
+    ) -> Self::Rating;
+
+    // New ratings for the members of both teams after a game where team A got score_team_a,
+    // each member given with their own K-factor and returned in the same order
+    fn rate_team_game(
+        team_a: &[(Self::Rating, u32)],
+        team_b: &[(Self::Rating, u32)],
+        score_team_a: f32,
+    ) -> (Vec<Self::Rating>, Vec<Self::Rating>);
+
+    // Rating moved the given fraction of the way towards the target, used to soft reset ratings between seasons
+    fn move_towards(rating: &Self::Rating, target: &Self::Rating, fraction: f32) -> Self::Rating;
+}
+
+/**
+ * Classic Elo, where the rating is a single integer
+ */
+pub struct Elo;
+
+impl RatingSystem for Elo {
+    type Rating = EloRating;
+
+    fn default_initial_rating() -> EloRating {
+        1000
+    }
+
+    fn ranking_key(rating: &EloRating) -> i64 {
+        *rating as i64
+    }
+
+    fn rate_game(
+        player_a: &EloRating,
+        player_b: &EloRating,
+        score_player_a: f32,
+        k_factor_player_a: u32,
+        k_factor_player_b: u32,
+    ) -> (EloRating, EloRating) {
+        skill_rating::elo::game(
+            *player_a,
+            *player_b,
+            score_player_a,
+            k_factor_player_a,
+            k_factor_player_b,
+        )
+    }
+
+    fn rate_multiplayer_game(
+        player: &EloRating,
+        opponents: &[(EloRating, f32)],
+        k_factor: u32,
+    ) -> EloRating {
+        if opponents.is_empty() {
+            return *player;
+        }
+
+        // Pairwise decomposition: the K-factor is split between all the pairings of the player
+        let pairing_k_factor = k_factor as f32 / opponents.len() as f32;
+
+        let change: f32 = opponents
+            .iter()
+            .map(|(opponent, score)| {
+                pairing_k_factor * (score - elo_expected_score(*player as f32, *opponent as f32))
+            })
+            .sum();
+
+        (*player as f32 + change).round().max(0.0) as EloRating
+    }
+
+    fn rate_team_game(
+        team_a: &[(EloRating, u32)],
+        team_b: &[(EloRating, u32)],
+        score_team_a: f32,
+    ) -> (Vec<EloRating>, Vec<EloRating>) {
+        // The expected score comes from the average rating of each team,
+        // and each member moves by their own K-factor times the team's surprise
+        let expected_team_a = elo_expected_score(average_elo(team_a), average_elo(team_b));
+
+        let apply_change = |team: &[(EloRating, u32)], score: f32, expected: f32| {
+            team.iter()
+                .map(|(rating, k_factor)| {
+                    (*rating as f32 + *k_factor as f32 * (score - expected))
+                        .round()
+                        .max(0.0) as EloRating
+                })
+                .collect()
+        };
+
+        (
+            apply_change(team_a, score_team_a, expected_team_a),
+            apply_change(team_b, 1.0 - score_team_a, 1.0 - expected_team_a),
+        )
+    }
+
+    fn move_towards(rating: &EloRating, target: &EloRating, fraction: f32) -> EloRating {
+        (*rating as f32 + (*target as f32 - *rating as f32) * fraction)
+            .round()
+            .max(0.0) as EloRating
+    }
+}
+
+fn average_elo(team: &[(EloRating, u32)]) -> f32 {
+    if team.is_empty() {
+        return 0.0;
+    }
+
+    team.iter().map(|(rating, _)| *rating as f32).sum::<f32>() / team.len() as f32
+}
+
+fn elo_expected_score(player: f32, opponent: f32) -> f32 {
+    1.0 / (1.0 + 10_f32.powf((opponent - player) / 400.0))
+}
+
+/**
+ * Glicko-2, which tracks the rating deviation and volatility of each player along with their rating
+ *
+ * The K-factor is ignored: the rating deviation already determines how much a rating can move
+ */
+pub struct Glicko2;
+
+impl Glicko2 {
+    // Constrains the change in volatility over time
+    pub const TAU: f64 = 0.5;
+}
+
+impl RatingSystem for Glicko2 {
+    type Rating = Glicko2Rating;
+
+    fn default_initial_rating() -> Glicko2Rating {
+        Glicko2Rating {
+            rating: 1500.0,
+            deviation: 350.0,
+            volatility: 0.06,
+        }
+    }
+
+    fn ranking_key(rating: &Glicko2Rating) -> i64 {
+        rating.rating.round() as i64
+    }
+
+    fn rate_game(
+        player_a: &Glicko2Rating,
+        player_b: &Glicko2Rating,
+        score_player_a: f32,
+        _k_factor_player_a: u32,
+        _k_factor_player_b: u32,
+    ) -> (Glicko2Rating, Glicko2Rating) {
+        glicko2::game(*player_a, *player_b, score_player_a, Self::TAU)
+    }
+
+    fn rate_multiplayer_game(
+        player: &Glicko2Rating,
+        opponents: &[(Glicko2Rating, f32)],
+        _k_factor: u32,
+    ) -> Glicko2Rating {
+        // All the pairings are treated as games played in the same rating period
+        let games: Vec<(Glicko2Rating, f64)> = opponents
+            .iter()
+            .map(|(opponent, score)| (*opponent, *score as f64))
+            .collect();
+
+        glicko2::rate(*player, &games, Self::TAU)
+    }
+
+    fn rate_team_game(
+        team_a: &[(Glicko2Rating, u32)],
+        team_b: &[(Glicko2Rating, u32)],
+        score_team_a: f32,
+    ) -> (Vec<Glicko2Rating>, Vec<Glicko2Rating>) {
+        // Each member plays a single game against a composite player that aggregates the other team
+        let composite_a =
+            glicko2::composite(&team_a.iter().map(|(rating, _)| *rating).collect::<Vec<_>>());
+        let composite_b =
+            glicko2::composite(&team_b.iter().map(|(rating, _)| *rating).collect::<Vec<_>>());
+
+        let rate_team = |team: &[(Glicko2Rating, u32)], opponent: Glicko2Rating, score: f64| {
+            team.iter()
+                .map(|(rating, _)| glicko2::rate(*rating, &[(opponent, score)], Self::TAU))
+                .collect()
+        };
+
+        (
+            rate_team(team_a, composite_b, score_team_a as f64),
+            rate_team(team_b, composite_a, 1.0 - score_team_a as f64),
+        )
+    }
+
+    fn move_towards(
+        rating: &Glicko2Rating,
+        target: &Glicko2Rating,
+        fraction: f32,
+    ) -> Glicko2Rating {
+        // The deviation moves as well, so that the new season also brings back some uncertainty
+        let fraction = fraction as f64;
+
+        Glicko2Rating {
+            rating: rating.rating + (target.rating - rating.rating) * fraction,
+            deviation: rating.deviation + (target.deviation - rating.deviation) * fraction,
+            volatility: rating.volatility,
+        }
+    }
+}
+
+pub(crate) fn rating_to_bytes<R: RatingSystem>(
+    rating: &R::Rating,
+) -> ExternResult<SerializedBytes> {
+    let bytes = encode(rating)?;
+
+    Ok(SerializedBytes::from(UnsafeBytes::from(bytes)))
+}
+
+pub(crate) fn rating_from_bytes<R: RatingSystem>(
+    bytes: &SerializedBytes,
+) -> ExternResult<R::Rating> {
+    let rating: R::Rating = decode(bytes.bytes())?;
+
+    Ok(rating)
+}
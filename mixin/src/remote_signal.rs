@@ -0,0 +1,102 @@
+use hdk::prelude::holo_hash::*;
+use hdk::prelude::*;
+
+use crate::{
+    countersigning::{
+        common::{is_known_counterparty, CountersigningError},
+        grants::receive_countersigning_capability,
+        proposals::have_i_proposed_to,
+    },
+    elo_rating_system::EloRatingSystem,
+    game_result::{
+        dispute::{is_player_of_my_game_result, sync_my_ranking_with_disputes},
+        EloSignal,
+    },
+};
+
+/**
+ * The signals that agents send to each other, as opposed to the EloSignals emitted to the UI
+ */
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum EloRemoteSignal {
+    // Sent by the opponent who disputed one of my game results
+    GameResultDisputed {
+        game_result_hash: EntryHashB64,
+        reason: String,
+    },
+    // Sent by the opponent who rejected one of my proposals
+    GameResultProposalRejected {
+        game_result_hash: EntryHashB64,
+    },
+    // Sent by the opponent who granted me access to their countersigning functions
+    CountersigningCapabilityGranted {
+        secret: CapSecret,
+        game_id: Option<String>,
+    },
+    // Sent by the agent who started a countersigning session that failed
+    CountersigningSessionAbandoned {
+        game_result_hash: EntryHashB64,
+        unlocks_at: Timestamp,
+        error: CountersigningError,
+    },
+}
+
+/**
+ * Handles the signals sent by other agents, and forwards them to the UI
+ *
+ * Each signal is only accepted from the agent who could have sent it, so other agents can't make me
+ * commit entries or show fake notifications
+ */
+pub fn handle_elo_remote_signal<S: EloRatingSystem>(signal: EloRemoteSignal) -> ExternResult<()> {
+    let sender = AgentPubKeyB64::from(call_info()?.provenance);
+
+    match signal {
+        EloRemoteSignal::GameResultDisputed {
+            game_result_hash,
+            reason,
+        } => {
+            if !is_player_of_my_game_result(&game_result_hash.clone().into(), &sender)? {
+                return Err(WasmError::Guest(
+                    "Only the opponents of my game results can dispute them".into(),
+                ));
+            }
+            sync_my_ranking_with_disputes::<S>()?;
+
+            emit_signal(EloSignal::GameResultDisputed {
+                game_result_hash,
+                reason,
+            })
+        }
+        EloRemoteSignal::GameResultProposalRejected { game_result_hash } => {
+            if !have_i_proposed_to(&game_result_hash.clone().into(), &sender)? {
+                return Err(WasmError::Guest(
+                    "Only the opponent to whom I proposed a game result can reject it".into(),
+                ));
+            }
+
+            emit_signal(EloSignal::GameResultProposalRejected { game_result_hash })
+        }
+        // The secret is kept out of the UI
+        EloRemoteSignal::CountersigningCapabilityGranted { secret, game_id } => {
+            receive_countersigning_capability(sender.into(), secret, game_id)
+        }
+        EloRemoteSignal::CountersigningSessionAbandoned {
+            game_result_hash,
+            unlocks_at,
+            error,
+        } => {
+            if !is_known_counterparty::<S>(&sender)? {
+                return Err(WasmError::Guest(
+                    "Only the agents I may be countersigning with can abandon a session".into(),
+                ));
+            }
+
+            emit_signal(EloSignal::CountersigningSessionAbandoned {
+                game_result_hash,
+                unlocks_at,
+                error,
+            })
+        }
+    }
+}
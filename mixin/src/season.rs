@@ -0,0 +1,73 @@
+use hdk::prelude::*;
+
+use crate::{elo_ranking::get_whole_ranking, EloRanking, EloRatingSystem};
+
+pub(crate) fn season_for<S: EloRatingSystem>(
+    game_info: &SerializedBytes,
+) -> ExternResult<Option<String>> {
+    let info = S::GameInfo::try_from(game_info.clone())
+        .or(Err(WasmError::Guest("Could not convert game info".into())))?;
+
+    Ok(S::season(&info))
+}
+
+/**
+ * The season that is being played now
+ */
+pub(crate) fn current_season<S: EloRatingSystem>() -> ExternResult<Option<String>> {
+    Ok(S::season_at(sys_time()?))
+}
+
+/**
+ * Only game results for the season that is being played can be published, so that the past seasons stay as they ended
+ */
+pub(crate) fn ensure_season_is_open<S: EloRatingSystem>(
+    game_info: &SerializedBytes,
+) -> ExternResult<()> {
+    match is_season_open_at::<S>(game_info, sys_time()?)? {
+        true => Ok(()),
+        false => Err(WasmError::Guest(format!(
+            "The season {} is not being played anymore",
+            season_for::<S>(game_info)?.unwrap_or_default()
+        ))),
+    }
+}
+
+/**
+ * Whether the season of the game was being played at the given time,
+ * which validation takes from the header so that its result doesn't change over time
+ */
+pub(crate) fn is_season_open_at<S: EloRatingSystem>(
+    game_info: &SerializedBytes,
+    timestamp: Timestamp,
+) -> ExternResult<bool> {
+    let season_at = S::season_at(timestamp);
+
+    Ok(season_at.is_none() || season_for::<S>(game_info)?.eq(&season_at))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetSeasonStandingsInput {
+    pub season: String,
+    pub category: Option<String>,
+}
+
+/**
+ * The final ranking of a season that has already ended, in the given category
+ *
+ * This needs to walk the whole ranking of the season, so it's meant to be called sparingly
+ */
+pub fn get_season_standings<S: EloRatingSystem>(
+    season: String,
+    category: Option<String>,
+) -> ExternResult<EloRanking> {
+    let season = Some(season);
+
+    if season.eq(&current_season::<S>()?) {
+        return Err(WasmError::Guest(
+            "The season is still being played, use get_elo_ranking_chunk to get its ranking".into(),
+        ));
+    }
+
+    get_whole_ranking(&category, &season)
+}
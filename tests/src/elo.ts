@@ -1,5 +1,11 @@
 import { Orchestrator, Player, Cell } from "@holochain/tryorama";
-import { config, installation, sleep } from "./utils";
+import {
+  config,
+  installation,
+  sleep,
+  decodeEloUpdate,
+  newGameResult,
+} from "./utils";
 import { serializeHash } from "@holochain-open-dev/core-types";
 
 export default (orchestrator: Orchestrator<any>) =>
@@ -39,10 +45,11 @@ export default (orchestrator: Orchestrator<any>) =>
 
     // When two concurrent calls occur, the second one fails
 
-    let game_result_hash = await alice.call("elo", "publish_result", [
-      bobKey,
-      1.0,
-    ]);
+    let game_result_hash = await alice.call(
+      "elo",
+      "publish_result",
+      await newGameResult(alice, bobKey, 1.0)
+    );
     t.ok(game_result_hash);
 
     await sleep(4000);
@@ -56,14 +63,20 @@ export default (orchestrator: Orchestrator<any>) =>
     let bobGameResult = gameResults[bobKey][0];
 
     t.equal(gameResults[aliceKey].length, 1);
-    t.deepEqual(aliceGameResult[1].player_a, {
+    t.deepEqual(decodeEloUpdate(aliceGameResult[1].player_a), {
       player_address: aliceKey,
-      current_elo: 1016,
+      current_rating: 1016,
+      games_played: 1,
+      peak_ranking_key: 1016,
+      season: null,
       previous_game_result: null,
     });
-    t.deepEqual(aliceGameResult[1].player_b, {
+    t.deepEqual(decodeEloUpdate(aliceGameResult[1].player_b), {
       player_address: bobKey,
-      current_elo: 984,
+      current_rating: 984,
+      games_played: 1,
+      peak_ranking_key: 1000,
+      season: null,
       previous_game_result: null,
     });
     t.equal(aliceGameResult[1].score_player_a, 1);
@@ -73,14 +86,21 @@ export default (orchestrator: Orchestrator<any>) =>
       aliceKey,
       bobKey,
     ]);
-    t.deepEqual(elos, { [aliceKey]: 1016, [bobKey]: 984 });
+    t.deepEqual(elos, {
+      [aliceKey]: { rating: 1016, games_played: 1, provisional: false },
+      [bobKey]: { rating: 984, games_played: 1, provisional: false },
+    });
 
     let previousAliceGameResultHash = serializeHash(aliceGameResult[0].hash);
     let previousBobGameResultHash = serializeHash(bobGameResult[0].hash);
 
     await sleep(4000);
 
-    let outcome = await bob.call("elo", "publish_result", [aliceKey, 0.0]);
+    let outcome = await bob.call(
+      "elo",
+      "publish_result",
+      await newGameResult(bob, aliceKey, 0.0)
+    );
     t.ok(outcome);
     game_result_hash = outcome.game_result_hash;
 
@@ -93,14 +113,20 @@ export default (orchestrator: Orchestrator<any>) =>
 
     aliceGameResult = gameResults[aliceKey][1];
     bobGameResult = gameResults[bobKey][1];
-    t.deepEqual(aliceGameResult[1].player_b, {
+    t.deepEqual(decodeEloUpdate(aliceGameResult[1].player_b), {
       player_address: aliceKey,
-      current_elo: 1030,
+      current_rating: 1030,
+      games_played: 2,
+      peak_ranking_key: 1030,
+      season: null,
       previous_game_result: previousAliceGameResultHash,
     });
-    t.deepEqual(aliceGameResult[1].player_a, {
+    t.deepEqual(decodeEloUpdate(aliceGameResult[1].player_a), {
       player_address: bobKey,
-      current_elo: 970,
+      current_rating: 970,
+      games_played: 2,
+      peak_ranking_key: 1000,
+      season: null,
       previous_game_result: previousBobGameResultHash,
     });
     t.equal(aliceGameResult[1].score_player_a, 0);
@@ -110,26 +136,34 @@ export default (orchestrator: Orchestrator<any>) =>
       aliceKey,
       bobKey,
     ]);
-    t.equal(elos[aliceKey], 1030);
-    t.equal(elos[bobKey], 970);
+    t.equal(elos[aliceKey].rating, 1030);
+    t.equal(elos[bobKey].rating, 970);
 
     await carol_player.shutdown();
 
     try {
-      outcome = await bob.call("elo", "publish_result", [carolKey, 0.0]);
+      outcome = await bob.call(
+        "elo",
+        "publish_result",
+        await newGameResult(bob, carolKey, 0.0)
+      );
       t.ok(false);
     } catch (e) {
       t.ok(true);
     }
 
-    await bob.call("elo", "publish_game_result_and_flag", [carolKey, 1.0]);
+    await bob.call(
+      "elo",
+      "publish_game_result_and_flag",
+      await newGameResult(bob, carolKey, 1.0)
+    );
 
     elos = await bob.call("elo", "get_elo_rating_for_agents", [
       carolKey,
       bobKey,
     ]);
-    t.equal(elos[carolKey], 1000);
-    t.equal(elos[bobKey], 987);
+    t.equal(elos[carolKey].rating, 1000);
+    t.equal(elos[bobKey].rating, 987);
     gameResults = await bob.call("elo", "get_game_results_for_agents", [
       carolKey,
     ]);
@@ -158,19 +192,47 @@ export default (orchestrator: Orchestrator<any>) =>
       bobKey,
       aliceKey,
     ]);
-    t.equal(1030, elos[aliceKey]);
-    t.equal(987, elos[bobKey]);
-    t.equal(983, elos[carolKey]);
+    t.equal(1030, elos[aliceKey].rating);
+    t.equal(987, elos[bobKey].rating);
+    t.equal(983, elos[carolKey].rating);
 
     let eloRanking = await bob.call("elo", "get_elo_ranking_chunk", {
       agentCount: 10,
     });
     t.deepEqual(
       {
-        987: [bobKey],
-        983: [carolKey],
-        1030: [aliceKey],
+        987: [{ agent_pub_key: bobKey, provisional: false }],
+        983: [{ agent_pub_key: carolKey, provisional: false }],
+        1030: [{ agent_pub_key: aliceKey, provisional: false }],
+      },
+      eloRanking.ranking
+    );
+    t.notOk(eloRanking.has_more);
+
+    let firstPage = await bob.call("elo", "get_elo_ranking_chunk", {
+      agentCount: 2,
+      direction: "Ascending",
+    });
+    t.deepEqual(
+      {
+        983: [{ agent_pub_key: carolKey, provisional: false }],
+        987: [{ agent_pub_key: bobKey, provisional: false }],
+      },
+      firstPage.ranking
+    );
+    t.ok(firstPage.has_more);
+
+    let secondPage = await bob.call("elo", "get_elo_ranking_chunk", {
+      fromElo: firstPage.next_cursor.from_elo,
+      fromAgent: firstPage.next_cursor.from_agent,
+      agentCount: 2,
+      direction: "Ascending",
+    });
+    t.deepEqual(
+      {
+        1030: [{ agent_pub_key: aliceKey, provisional: false }],
       },
-      eloRanking
+      secondPage.ranking
     );
+    t.notOk(secondPage.has_more);
   });
@@ -0,0 +1,152 @@
+import { Orchestrator, Player, Cell } from "@holochain/tryorama";
+import {
+  config,
+  installation,
+  sleep,
+  decodeEloUpdate,
+  newGameResult,
+} from "./utils";
+import { serializeHash } from "@holochain-open-dev/core-types";
+
+export default (orchestrator: Orchestrator<any>) =>
+  orchestrator.registerScenario(
+    "flag resolution rebases on the games played while offline",
+    async (s, t) => {
+      const [alice_player, bob_player, carol_player]: Player[] =
+        await s.players([config, config, config]);
+
+      const [[alice_happ]] = await alice_player.installAgentsHapps(
+        installation
+      );
+      const [[bob_happ]] = await bob_player.installAgentsHapps(installation);
+      const [[carol_happ]] = await carol_player.installAgentsHapps(
+        installation
+      );
+
+      await s.shareAllNodes([alice_player, bob_player, carol_player]);
+
+      const alice = alice_happ.cells.find((cell) =>
+        cell.cellRole.includes("/example-elo.dna")
+      ) as Cell;
+      const bob = bob_happ.cells.find((cell) =>
+        cell.cellRole.includes("/example-elo.dna")
+      ) as Cell;
+      const carol = carol_happ.cells.find((cell) =>
+        cell.cellRole.includes("/example-elo.dna")
+      ) as Cell;
+
+      const aliceKey = serializeHash(alice.cellId[1]);
+      const bobKey = serializeHash(bob.cellId[1]);
+      const carolKey = serializeHash(carol.cellId[1]);
+
+      await sleep(4000);
+
+      // Bob beats carol while carol is offline, so bob flags the result
+      await carol_player.shutdown();
+
+      await bob.call(
+        "elo",
+        "publish_game_result_and_flag",
+        await newGameResult(bob, carolKey, 1.0)
+      );
+
+      let elos = await bob.call("elo", "get_elo_rating_for_agents", [
+        carolKey,
+        bobKey,
+      ]);
+      t.equal(elos[bobKey].rating, 1016);
+      t.equal(elos[carolKey].rating, 1000);
+
+      // Before resolving the flag, carol beats alice
+      await carol_player.startup({});
+
+      await sleep(4000);
+
+      await carol.call(
+        "elo",
+        "publish_result",
+        await newGameResult(carol, aliceKey, 1.0)
+      );
+
+      await sleep(10000);
+
+      let gameResults = await carol.call(
+        "elo",
+        "get_game_results_for_agents",
+        [carolKey]
+      );
+      t.equal(gameResults[carolKey].length, 1);
+      const carolAliceGameResultHash = serializeHash(
+        gameResults[carolKey][0][0].hash
+      );
+
+      elos = await carol.call("elo", "get_elo_rating_for_agents", [
+        carolKey,
+        aliceKey,
+      ]);
+      t.equal(elos[carolKey].rating, 1016);
+      t.equal(elos[aliceKey].rating, 984);
+
+      await carol.call(
+        "elo",
+        "scheduled_try_resolve_unpublished_game_results",
+        null
+      );
+
+      await sleep(25000);
+
+      gameResults = await bob.call("elo", "get_game_results_for_agents", [
+        bobKey,
+        carolKey,
+      ]);
+      t.equal(gameResults[bobKey].length, 1);
+      t.equal(gameResults[carolKey].length, 2);
+
+      // Carol committed a new entry, rebased on her game against alice, instead of the one flagged by bob
+      const flaggedGameResultHash = serializeHash(
+        gameResults[bobKey][0][0].hash
+      );
+      const rebasedGameResultHash = serializeHash(
+        gameResults[carolKey][1][0].hash
+      );
+      t.notEqual(rebasedGameResultHash, flaggedGameResultHash);
+
+      // 1016 losing against 1000 with K 32
+      const rebasedGameResult = gameResults[carolKey][1][1];
+      t.deepEqual(decodeEloUpdate(rebasedGameResult.player_b), {
+        player_address: carolKey,
+        current_rating: 999,
+        games_played: 2,
+        peak_ranking_key: 1016,
+        season: null,
+        previous_game_result: carolAliceGameResultHash,
+      });
+
+      // The EloUpdate of bob is kept as he published it
+      t.deepEqual(decodeEloUpdate(rebasedGameResult.player_a), {
+        player_address: bobKey,
+        current_rating: 1016,
+        games_played: 1,
+        peak_ranking_key: 1016,
+        season: null,
+        previous_game_result: null,
+      });
+
+      // The flag was resolved by the commit, so it's not retried
+      const unpublished = await carol.call(
+        "elo",
+        "clean_stale_unpublished_game_flags",
+        null
+      );
+      t.equal(unpublished.length, 0);
+
+      elos = await bob.call("elo", "get_elo_rating_for_agents", [
+        carolKey,
+        bobKey,
+        aliceKey,
+      ]);
+      t.equal(elos[aliceKey].rating, 984);
+      t.equal(elos[bobKey].rating, 1016);
+      t.equal(elos[carolKey].rating, 999);
+    }
+  );
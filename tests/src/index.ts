@@ -1,8 +1,16 @@
 import { Orchestrator } from "@holochain/tryorama";
 
 import elo from "./elo";
+import flagRebase from "./flag-rebase";
+import publishFallback from "./publish-fallback";
+import queue from "./queue";
+import rankingNames from "./ranking-names";
 let orchestrator: Orchestrator<any>;
 
 orchestrator = new Orchestrator();
 elo(orchestrator);
+flagRebase(orchestrator);
+publishFallback(orchestrator);
+queue(orchestrator);
+rankingNames(orchestrator);
 orchestrator.run();
@@ -0,0 +1,126 @@
+import { Orchestrator, Player, Cell } from "@holochain/tryorama";
+import {
+  config,
+  installation,
+  sleep,
+  sleepUntil,
+  newGameResult,
+} from "./utils";
+import { serializeHash } from "@holochain-open-dev/core-types";
+
+export default (orchestrator: Orchestrator<any>) => {
+  orchestrator.registerScenario(
+    "publishing falls back to a flagged result when the opponent is offline",
+    async (s, t) => {
+      const [alice_player, bob_player]: Player[] = await s.players([
+        config,
+        config,
+      ]);
+
+      const [[alice_happ]] = await alice_player.installAgentsHapps(
+        installation
+      );
+      const [[bob_happ]] = await bob_player.installAgentsHapps(installation);
+
+      await s.shareAllNodes([alice_player, bob_player]);
+
+      const alice = alice_happ.cells.find((cell) =>
+        cell.cellRole.includes("/example-elo.dna")
+      ) as Cell;
+      const bob = bob_happ.cells.find((cell) =>
+        cell.cellRole.includes("/example-elo.dna")
+      ) as Cell;
+
+      const aliceKey = serializeHash(alice.cellId[1]);
+      const bobKey = serializeHash(bob.cellId[1]);
+
+      await sleep(4000);
+
+      // While both are online the game result is countersigned
+      let outcome = await alice.call(
+        "elo",
+        "publish_game_result",
+        await newGameResult(alice, bobKey, 1.0)
+      );
+      t.equal(outcome.path, "Countersigned");
+      t.deepEqual(outcome.countersigning_errors, []);
+      t.ok(outcome.game_result_hash);
+
+      await sleep(4000);
+
+      // With bob offline no session is started, so alice's game result is flagged for them right away
+      await bob_player.shutdown();
+
+      outcome = await alice.call(
+        "elo",
+        "publish_game_result",
+        await newGameResult(alice, bobKey, 1.0)
+      );
+      t.equal(outcome.path, "Flagged");
+      t.equal(outcome.countersigning_errors.length, 1);
+      t.equal(outcome.countersigning_errors[0].type, "OpponentUnreachable");
+      t.ok(outcome.game_result_hash);
+
+      const elos = await alice.call("elo", "get_elo_rating_for_agents", [
+        aliceKey,
+        bobKey,
+      ]);
+      t.equal(elos[aliceKey].rating, 1030);
+      t.equal(elos[bobKey].rating, 984);
+    }
+  );
+
+  orchestrator.registerScenario(
+    "publishing falls back when the opponent goes offline during the session",
+    async (s, t) => {
+      const [alice_player, bob_player]: Player[] = await s.players([
+        config,
+        config,
+      ]);
+
+      const [[alice_happ]] = await alice_player.installAgentsHapps(
+        installation
+      );
+      const [[bob_happ]] = await bob_player.installAgentsHapps(installation);
+
+      await s.shareAllNodes([alice_player, bob_player]);
+
+      const alice = alice_happ.cells.find((cell) =>
+        cell.cellRole.includes("/example-elo.dna")
+      ) as Cell;
+      const bob = bob_happ.cells.find((cell) =>
+        cell.cellRole.includes("/example-elo.dna")
+      ) as Cell;
+
+      const bobKey = serializeHash(bob.cellId[1]);
+
+      await sleep(4000);
+
+      // Bob goes offline once alice has already accepted the preflight and locked her source chain
+      const publishing = alice.call(
+        "elo",
+        "publish_game_result",
+        await newGameResult(alice, bobKey, 1.0)
+      );
+      await sleep(100);
+      await bob_player.shutdown();
+
+      const outcome = await publishing;
+      t.ok(outcome.path === "Countersigned" || outcome.path.Locked);
+      t.ok(outcome.game_result_hash);
+
+      // Alice's source chain is not left locked after the window of the failed session
+      if (outcome.path.Locked) {
+        await sleepUntil(outcome.path.Locked.until);
+      }
+      const retriedOutcome = await alice.call(
+        "elo",
+        "publish_game_result",
+        await newGameResult(alice, bobKey, 1.0)
+      );
+      t.equal(retriedOutcome.path, "Flagged");
+      t.equal(retriedOutcome.countersigning_errors.length, 1);
+      t.ok(retriedOutcome.game_result_hash);
+    }
+  );
+};
@@ -0,0 +1,91 @@
+import { Orchestrator, Player, Cell } from "@holochain/tryorama";
+import {
+  config,
+  installation,
+  sleep,
+  decodeEloUpdate,
+  newGameResult,
+} from "./utils";
+import { serializeHash } from "@holochain-open-dev/core-types";
+
+export default (orchestrator: Orchestrator<any>) =>
+  orchestrator.registerScenario(
+    "concurrent game results are queued and published one at a time",
+    async (s, t) => {
+      const [alice_player, bob_player]: Player[] = await s.players([
+        config,
+        config,
+      ]);
+
+      const [[alice_happ]] = await alice_player.installAgentsHapps(
+        installation
+      );
+      const [[bob_happ]] = await bob_player.installAgentsHapps(installation);
+
+      await s.shareAllNodes([alice_player, bob_player]);
+
+      const alice = alice_happ.cells.find((cell) =>
+        cell.cellRole.includes("/example-elo.dna")
+      ) as Cell;
+      const bob = bob_happ.cells.find((cell) =>
+        cell.cellRole.includes("/example-elo.dna")
+      ) as Cell;
+
+      const aliceKey = serializeHash(alice.cellId[1]);
+      const bobKey = serializeHash(bob.cellId[1]);
+
+      await sleep(4000);
+
+      const firstGame = await newGameResult(alice, bobKey, 1.0);
+      const secondGame = await newGameResult(alice, bobKey, 1.0);
+
+      // Both calls succeed, and the game results are queued in order
+      await Promise.all([
+        alice.call("elo", "queue_result", firstGame),
+        alice.call("elo", "queue_result", secondGame),
+      ]);
+
+      let queue = await alice.call("elo", "get_publication_queue", null);
+      t.equal(queue.length, 2);
+
+      // Nothing is published from the calls that queue the game results
+      t.equal(queue[0].status.type, "Queued");
+      t.equal(queue[1].status.type, "Queued");
+
+      // The scheduled job starts each publication in one run and publishes it in the next one
+      for (let i = 0; i < 4; i++) {
+        await sleep(10000);
+        await alice.call(
+          "elo",
+          "scheduled_try_resolve_unpublished_game_results",
+          null
+        );
+      }
+
+      await sleep(10000);
+
+      queue = await alice.call("elo", "get_publication_queue", null);
+      t.equal(queue[0].status.type, "Published");
+      t.equal(queue[1].status.type, "Published");
+
+      const gameResults = await bob.call(
+        "elo",
+        "get_game_results_for_agents",
+        [aliceKey]
+      );
+      t.equal(gameResults[aliceKey].length, 2);
+
+      // The second game result is rebased on the first one
+      const firstGameResultHash = serializeHash(
+        gameResults[aliceKey][0][0].hash
+      );
+      t.deepEqual(decodeEloUpdate(gameResults[aliceKey][1][1].player_a), {
+        player_address: aliceKey,
+        current_rating: 1030,
+        games_played: 2,
+        peak_ranking_key: 1030,
+        season: null,
+        previous_game_result: firstGameResultHash,
+      });
+    }
+  );
@@ -0,0 +1,74 @@
+import { Orchestrator, Player, Cell } from "@holochain/tryorama";
+import { config, installation, sleep, newGameResult } from "./utils";
+import { serializeHash } from "@holochain-open-dev/core-types";
+
+export default (orchestrator: Orchestrator<any>) =>
+  orchestrator.registerScenario(
+    "category and season pairs with similar names have their own rankings",
+    async (s, t) => {
+      const [alice_player, bob_player]: Player[] = await s.players([
+        config,
+        config,
+      ]);
+
+      const [[alice_happ]] = await alice_player.installAgentsHapps(
+        installation
+      );
+      const [[bob_happ]] = await bob_player.installAgentsHapps(installation);
+
+      await s.shareAllNodes([alice_player, bob_player]);
+
+      const alice = alice_happ.cells.find((cell) =>
+        cell.cellRole.includes("/example-elo.dna")
+      ) as Cell;
+      const bob = bob_happ.cells.find((cell) =>
+        cell.cellRole.includes("/example-elo.dna")
+      ) as Cell;
+
+      const aliceKey = serializeHash(alice.cellId[1]);
+      const bobKey = serializeHash(bob.cellId[1]);
+
+      await sleep(4000);
+
+      // Joining the category and the season with a separator would give the same name to both rankings
+      await alice.call("elo", "publish_result", {
+        ...(await newGameResult(alice, bobKey, 1.0)),
+        category: "a_b",
+      });
+
+      await sleep(4000);
+
+      await bob.call("elo", "publish_result", {
+        ...(await newGameResult(bob, aliceKey, 1.0)),
+        category: "a",
+        season: "b",
+      });
+
+      await sleep(4000);
+
+      const categoryRanking = await bob.call("elo", "get_elo_ranking_chunk", {
+        agentCount: 10,
+        category: "a_b",
+      });
+      t.deepEqual(
+        {
+          1016: [{ agent_pub_key: aliceKey, provisional: false }],
+          984: [{ agent_pub_key: bobKey, provisional: false }],
+        },
+        categoryRanking.ranking
+      );
+
+      const seasonRanking = await alice.call("elo", "get_elo_ranking_chunk", {
+        agentCount: 10,
+        category: "a",
+        season: "b",
+      });
+      t.deepEqual(
+        {
+          1016: [{ agent_pub_key: bobKey, provisional: false }],
+          984: [{ agent_pub_key: aliceKey, provisional: false }],
+        },
+        seasonRanking.ranking
+      );
+    }
+  );
@@ -1,5 +1,6 @@
-import { Config, InstallAgentsHapps } from "@holochain/tryorama";
+import { Cell, Config, InstallAgentsHapps } from "@holochain/tryorama";
 import path from "path";
+import { decode } from "@msgpack/msgpack";
 import { fileURLToPath } from "url";
 const __filename = fileURLToPath(import.meta.url);
 const __dirname = path.dirname(__filename);
@@ -22,3 +23,24 @@ export const installation: InstallAgentsHapps = [
 
 export const sleep = (ms: number) =>
   new Promise((resolve) => setTimeout(() => resolve(null), ms));
+
+// Waits until a bit after the given Timestamp, which is in microseconds
+export const sleepUntil = (timestamp: number) =>
+  sleep(Math.max(timestamp / 1000 - Date.now(), 0) + 1000);
+
+// The ratings are stored serialized inside each EloUpdate
+export const decodeEloUpdate = (eloUpdate: any) => ({
+  ...eloUpdate,
+  current_rating: decode(eloUpdate.current_rating),
+});
+
+// Starts a new game against the opponent, returning the input to publish its result
+export const newGameResult = async (
+  player: Cell,
+  opponentKey: string,
+  myScore: number
+) => ({
+  game_hash: await player.call("elo", "create_game", opponentKey),
+  opponent: opponentKey,
+  my_score: myScore,
+});
@@ -112,9 +112,12 @@ export class EloRankingElement extends ScopedElementsMixin(LitElement) {
         <div class="flex-scrollable-container">
           <div class="flex-scrollable-y">
             <mwc-list noninteractive style="margin-right: 8px;">
-              ${rankingEntries.map(([eloRanking, agentsPubKeys]) =>
-                agentsPubKeys.map(agentPubKey =>
-                  this.renderPlayer(agentPubKey, parseInt(eloRanking))
+              ${rankingEntries.map(([eloRanking, rankedAgents]) =>
+                rankedAgents.map(rankedAgent =>
+                  this.renderPlayer(
+                    rankedAgent.agent_pub_key,
+                    parseInt(eloRanking)
+                  )
                 )
               )}
             </mwc-list>
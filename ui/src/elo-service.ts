@@ -2,7 +2,15 @@ import { AgentPubKeyB64, Dictionary } from '@holochain-open-dev/core-types';
 import { CellClient } from '@holochain-open-dev/cell-client';
 import { HoloHashed } from '@holochain/client';
 
-import { EloRanking, GameResult } from './types';
+import {
+  AgentRankPosition,
+  AgentRating,
+  EloRanking,
+  EloRankingChunk,
+  EloRankingCursor,
+  GameResult,
+  RankingDirection,
+} from './types';
 
 export class EloService {
   constructor(public cellClient: CellClient, protected zomeName: string) {}
@@ -15,17 +23,60 @@ export class EloService {
 
   public getEloRatingForAgents(
     agents: AgentPubKeyB64[]
-  ): Promise<Dictionary<number>> {
+  ): Promise<Dictionary<AgentRating>> {
     return this.callZome('get_elo_rating_for_agents', agents);
   }
 
+  public getEloRatingForAgentsInCategory(
+    agents: AgentPubKeyB64[],
+    category: string | undefined
+  ): Promise<Dictionary<AgentRating>> {
+    return this.callZome('get_elo_rating_for_agents_in_category', {
+      agentPubKeys: agents,
+      category,
+    });
+  }
+
   public getEloRankingChunk(
-    fromElo: number | undefined,
-    agentCount: number
-  ): Promise<EloRanking> {
+    cursor: EloRankingCursor | undefined,
+    agentCount: number,
+    direction: RankingDirection = 'Descending',
+    category: string | undefined = undefined,
+    season: string | undefined = undefined
+  ): Promise<EloRankingChunk> {
     return this.callZome('get_elo_ranking_chunk', {
-      fromElo,
+      fromElo: cursor?.from_elo,
+      fromAgent: cursor?.from_agent,
+      direction,
+      category,
+      season,
+      agentCount,
+    });
+  }
+
+  public getSeasonStandings(
+    season: string,
+    category: string | undefined = undefined
+  ): Promise<EloRanking> {
+    return this.callZome('get_season_standings', { season, category });
+  }
+
+  public getAgentRankPosition(
+    agentPubKey: AgentPubKeyB64,
+    category: string | undefined = undefined
+  ): Promise<AgentRankPosition> {
+    return this.callZome('get_agent_rank_position', { agentPubKey, category });
+  }
+
+  public getRankingNeighbourhood(
+    agentPubKey: AgentPubKeyB64,
+    agentCount: number,
+    category: string | undefined = undefined
+  ): Promise<EloRanking> {
+    return this.callZome('get_ranking_neighbourhood', {
+      agentPubKey,
       agentCount,
+      category,
     });
   }
 
@@ -110,7 +110,12 @@ export class EloStore {
       this.profilesStore.fetchAgentsProfiles(agents),
     ]);
 
-    this.#elosByAgent.update(e => ({ ...e, ...info[0] }));
+    const elos: { [key: string]: number } = {};
+    for (const [agent, agentRating] of Object.entries(info[0])) {
+      elos[agent] = agentRating.rating;
+    }
+
+    this.#elosByAgent.update(e => ({ ...e, ...elos }));
   }
 
   private async handleNewGameResult(
@@ -11,7 +11,7 @@ import flatten from 'lodash-es/flatten';
 
 import { AgentPubKeyB64 } from '@holochain-open-dev/core-types';
 import { EloService } from '../elo-service';
-import { EloRanking } from '../types';
+import { EloRanking, EloRankingCursor } from '../types';
 
 export interface ChunkedEloRanking {
   ranking: EloRanking;
@@ -23,6 +23,8 @@ export class EloRankingStore implements Readable<ChunkedEloRanking> {
     ranking: {},
     thereAreMoreChunksToFetch: true,
   });
+  // Last agent fetched, the next chunk starts right after them
+  #cursor: EloRankingCursor | undefined = undefined;
 
   constructor(
     protected eloService: EloService,
@@ -37,50 +39,26 @@ export class EloRankingStore implements Readable<ChunkedEloRanking> {
   async fetchNextChunk() {
     const existingRanking = get(this.#store).ranking;
 
-    const fromElo = this.newFromElo(existingRanking);
-
-    // This is needed to handle the case in which we already have some agents for a
-    // certain ELO and we want to fetch N more than those
-    const chunkSize =
-      this.chunkSize +
-      (fromElo && existingRanking[fromElo]
-        ? existingRanking[fromElo].length
-        : 0);
-
     const nextChunk = await this.eloService.getEloRankingChunk(
-      fromElo,
-      chunkSize
+      this.#cursor,
+      this.chunkSize
     );
-    let thereAreMoreChunksToFetch = false;
+    if (nextChunk.next_cursor) this.#cursor = nextChunk.next_cursor;
 
-    const pubKeysToFetch: AgentPubKeyB64[] = [];
+    const pubKeysToFetch: AgentPubKeyB64[] = flatten(
+      Object.values(nextChunk.ranking)
+    ).map(agent => agent.agent_pub_key);
 
-    for (const [ranking, agents] of Object.entries(nextChunk)) {
+    await this.profilesStore.fetchAgentsProfiles(pubKeysToFetch);
+
+    for (const [ranking, agents] of Object.entries(nextChunk.ranking)) {
       if (!existingRanking[ranking]) existingRanking[ranking] = [];
-      for (const agent of agents) {
-        if (!existingRanking[ranking].includes(agent)) {
-          if (pubKeysToFetch.length < this.chunkSize) {
-            pubKeysToFetch.push(agent);
-            existingRanking[ranking].push(agent);
-          } else {
-            thereAreMoreChunksToFetch = true;
-          }
-        }
-      }
+      existingRanking[ranking].push(...agents);
     }
 
-    await this.profilesStore.fetchAgentsProfiles(pubKeysToFetch);
-
     this.#store.set({
       ranking: existingRanking,
-      thereAreMoreChunksToFetch,
+      thereAreMoreChunksToFetch: nextChunk.has_more,
     });
   }
-
-  private newFromElo(ranking: EloRanking): number | undefined {
-    const elos = Object.keys(ranking).map(key => {return parseInt(key)});
-    if (elos.length === 0) return undefined;
-
-    return Math.min(...elos);
-  }
 }
@@ -7,16 +7,79 @@ import {
 
 export interface EloUpdate {
   player_address: AgentPubKeyB64;
-  current_elo: number;
+  // Rating after this game, msgpack-encoded by the rating system
+  current_rating: Uint8Array;
+  // Number of games the player has played, including this one
+  games_played: number;
+  // Highest ranking key the player has reached, including this game
+  peak_ranking_key: number;
+  // Season in which this game was played, if the zome uses seasons
+  season: string | undefined;
   // Will be None in the first GameResult entry for that player
   previous_game_result: HeaderHashB64 | undefined;
 }
 
 export interface GameResult {
+  game_id: string;
   player_a: EloUpdate;
   player_b: EloUpdate;
   score_player_a: number;
   game_info: any;
 }
 
-export type EloRanking = Dictionary<Array<AgentPubKeyB64>>;
+export interface MultiplayerGameResult {
+  game_id: string;
+  players: EloUpdate[];
+  // Same order as players: 1 is the winner, equal placements are ties
+  placements: number[];
+  game_info: any;
+}
+
+export interface TeamGameResult {
+  game_id: string;
+  team_a: EloUpdate[];
+  team_b: EloUpdate[];
+  score_team_a: number;
+  game_info: any;
+}
+
+export type AnyGameResult =
+  | GameResult
+  | MultiplayerGameResult
+  | TeamGameResult;
+
+export interface AgentRating {
+  rating: any;
+  games_played: number;
+  // Whether the agent is still within their provisional period
+  provisional: boolean;
+}
+
+export interface RankedAgent {
+  agent_pub_key: AgentPubKeyB64;
+  provisional: boolean;
+}
+
+export type EloRanking = Dictionary<Array<RankedAgent>>;
+
+export type RankingDirection = 'Ascending' | 'Descending';
+
+export interface EloRankingCursor {
+  from_elo: number;
+  from_agent: AgentPubKeyB64;
+}
+
+export interface EloRankingChunk {
+  ranking: EloRanking;
+  has_more: boolean;
+  next_cursor: EloRankingCursor | undefined;
+}
+
+export interface AgentRankPosition {
+  agent_pub_key: AgentPubKeyB64;
+  ranking_key: number;
+  position: number;
+  total_agents: number;
+  // Percentage of the other agents with a lower ranking key
+  percentile: number;
+}